@@ -0,0 +1,281 @@
+//! Complete archive for recordings with a bounded live window.
+//!
+//! With `live_window_segments` ffmpeg keeps only the last N segments in
+//! `{name}.m3u8` and deletes older ones. The archiver links every segment
+//! into `{name}_archive/` before that happens and maintains
+//! `{name}.archive.m3u8`, which finalize uses instead of the live playlist.
+
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    time::{Duration, interval},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::playlist::{Playlist, is_ad_marker, parse_segments, uri_path};
+
+/// How often the live playlist is checked for new segments. Segments stay
+/// on disk for at least the window length, so this only needs to be well
+/// below one segment duration.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn playlist_path(pending_dir: &Path, name: &str) -> PathBuf {
+    pending_dir.join(format!("{}.archive.m3u8", name))
+}
+
+pub fn dir_path(pending_dir: &Path, name: &str) -> PathBuf {
+    pending_dir.join(format!("{}_archive", name))
+}
+
+pub struct Archiver {
+    pending_dir: PathBuf,
+    name: String,
+    archived: HashSet<String>,
+    /// `#EXT-X-TARGETDURATION` of the archive: the longest segment so far
+    target_duration: u64,
+}
+
+impl Archiver {
+    /// Picks up an existing archive when a recording is resumed.
+    pub async fn open(pending_dir: &Path, name: &str) -> Result<Self> {
+        fs::create_dir_all(dir_path(pending_dir, name)).await?;
+        let existing = fs::read_to_string(playlist_path(pending_dir, name))
+            .await
+            .unwrap_or_default();
+        let existing = Playlist::parse(&existing);
+        let archived = existing
+            .segments
+            .iter()
+            .map(|s| file_name(&s.uri).to_string())
+            .collect();
+        Ok(Self {
+            pending_dir: pending_dir.to_path_buf(),
+            name: name.to_string(),
+            archived,
+            target_duration: existing.target_duration.unwrap_or_default(),
+        })
+    }
+
+    /// Archive new segments until `stop` is cancelled, then pick up the
+    /// ones written since the last poll.
+    pub async fn run(mut self, stop: CancellationToken) {
+        let mut tick = interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {}
+                _ = stop.cancelled() => break,
+            }
+            if let Err(e) = self.sync().await {
+                error!(name=%self.name, error=?e, "archiving segments failed");
+            }
+        }
+        if let Err(e) = self.sync().await {
+            error!(name=%self.name, error=?e, "archiving last segments failed");
+        }
+    }
+
+    /// Link segments listed in the live playlist that are not archived yet
+    /// and append them to the archive playlist.
+    pub async fn sync(&mut self) -> Result<()> {
+        let live = self.pending_dir.join(format!("{}.m3u8", self.name));
+        let Ok(content) = fs::read_to_string(&live).await else {
+            return Ok(());
+        };
+        let dir = dir_path(&self.pending_dir, &self.name);
+        let archive_pl = playlist_path(&self.pending_dir, &self.name);
+
+        let mut entries = String::new();
+        let mut added = Vec::new();
+        let mut target = self.target_duration;
+        for seg in parse_segments(&content) {
+            let file = file_name(&seg.uri).to_string();
+            if self.archived.contains(&file) || added.contains(&file) {
+                continue;
+            }
+            // relative to the playlist, e.g. `{name}/` with segment_subdir
+            let src = self.pending_dir.join(uri_path(&seg.uri));
+            if !link_segment(&src, &dir.join(&file)).await? {
+                warn!(name=%self.name, segment=%file, "segment was deleted before it could be archived");
+                self.archived.insert(file);
+                continue;
+            }
+            if seg.discontinuity {
+                entries.push_str("#EXT-X-DISCONTINUITY\n");
+            }
+            for tag in seg.tags.iter().filter(|t| is_ad_marker(t)) {
+                entries.push_str(tag);
+                entries.push('\n');
+            }
+            if let Some(pdt) = &seg.program_date_time {
+                entries.push_str(&format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", pdt));
+            }
+            entries.push_str(&format!(
+                "#EXTINF:{:.6},\n{}_archive/{}\n",
+                seg.duration, self.name, file
+            ));
+            target = target.max(seg.duration.round() as u64);
+            added.push(file);
+        }
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let target_line = format!("#EXT-X-TARGETDURATION:{}", target);
+        match fs::read_to_string(&archive_pl).await {
+            Ok(_) if target == self.target_duration => {
+                let mut f = fs::OpenOptions::new()
+                    .append(true)
+                    .open(&archive_pl)
+                    .await?;
+                f.write_all(entries.as_bytes()).await?;
+                f.flush().await?;
+            }
+            // a segment longer than the target so far: players reject the
+            // playlist unless the header is raised
+            Ok(old) => {
+                let mut content: String = old
+                    .lines()
+                    .map(|l| match l.starts_with("#EXT-X-TARGETDURATION:") {
+                        true => format!("{}\n", target_line),
+                        false => format!("{}\n", l),
+                    })
+                    .collect();
+                content.push_str(&entries);
+                replace_file(&archive_pl, &content).await?;
+            }
+            Err(_) => {
+                let content = format!(
+                    "#EXTM3U\n#EXT-X-VERSION:3\n{}\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:EVENT\n{}",
+                    target_line, entries
+                );
+                replace_file(&archive_pl, &content).await?;
+            }
+        }
+        self.target_duration = target;
+        self.archived.extend(added);
+        Ok(())
+    }
+}
+
+/// Hard-link (or copy) `src` to `dst`; `false` if `src` is already gone.
+/// A `dst` left by an interrupted sync is kept: it is a link to `src`, and
+/// copying onto it would truncate the live segment.
+async fn link_segment(src: &Path, dst: &Path) -> Result<bool> {
+    match fs::hard_link(src, dst).await {
+        Ok(()) => return Ok(true),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => return Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(_) => {}
+    }
+    match fs::copy(src, dst).await {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write `path` through a temporary file, so readers never see it half
+/// written.
+async fn replace_file(path: &Path, content: &str) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, content).await?;
+    fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+/// Spawn the archiver; `ArchiverHandle::finish` stops it.
+pub async fn spawn(pending_dir: &Path, name: &str) -> Result<ArchiverHandle> {
+    let archiver = Archiver::open(pending_dir, name).await?;
+    info!(%name, "archiving live window segments");
+    let stop = CancellationToken::new();
+    Ok(ArchiverHandle {
+        task: tokio::spawn(archiver.run(stop.clone())),
+        stop,
+        name: name.to_string(),
+    })
+}
+
+pub struct ArchiverHandle {
+    task: tokio::task::JoinHandle<()>,
+    stop: CancellationToken,
+    name: String,
+}
+
+impl ArchiverHandle {
+    /// Stop polling and wait until the segments written since the last
+    /// poll are archived. A sync in progress is completed first.
+    pub async fn finish(self) {
+        self.stop.cancel();
+        if let Err(e) = self.task.await {
+            error!(name=%self.name, error=?e, "segment archiver failed");
+        }
+    }
+}
+
+fn file_name(uri: &str) -> &str {
+    let path = uri_path(uri);
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[tokio::test]
+    async fn sync_keeps_linked_segments_and_skips_deleted_ones() {
+        let dir = TempDir::new();
+        let pending = dir.path();
+        let mut archiver = Archiver::open(pending, "cam1").await.unwrap();
+        std::fs::write(pending.join("cam1_000.ts"), b"segment 0").unwrap();
+        std::fs::write(pending.join("cam1_002.ts"), b"segment 2").unwrap();
+        // linked by a sync that was stopped before it wrote the playlist
+        std::fs::hard_link(
+            pending.join("cam1_000.ts"),
+            dir_path(pending, "cam1").join("cam1_000.ts"),
+        )
+        .unwrap();
+        std::fs::write(
+            pending.join("cam1.m3u8"),
+            "#EXTM3U\n#EXTINF:4.000000,\ncam1_000.ts\n#EXTINF:4.000000,\ncam1_001.ts\n\
+             #EXTINF:6.000000,\ncam1_002.ts\n",
+        )
+        .unwrap();
+
+        archiver.sync().await.unwrap();
+        assert_eq!(
+            std::fs::read(pending.join("cam1_000.ts")).unwrap(),
+            b"segment 0"
+        );
+        let archive =
+            Playlist::parse(&std::fs::read_to_string(playlist_path(pending, "cam1")).unwrap());
+        let uris: Vec<_> = archive.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            ["cam1_archive/cam1_000.ts", "cam1_archive/cam1_002.ts"]
+        );
+        assert_eq!(archive.target_duration, Some(6));
+
+        // a longer segment raises the target of the playlist written so far
+        std::fs::write(pending.join("cam1_003.ts"), b"segment 3").unwrap();
+        std::fs::write(
+            pending.join("cam1.m3u8"),
+            "#EXTM3U\n#EXTINF:6.000000,\ncam1_002.ts\n#EXTINF:8.000000,\ncam1_003.ts\n",
+        )
+        .unwrap();
+        archiver.sync().await.unwrap();
+        let archive =
+            Playlist::parse(&std::fs::read_to_string(playlist_path(pending, "cam1")).unwrap());
+        assert_eq!(archive.segments.len(), 3);
+        assert_eq!(archive.target_duration, Some(8));
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use tokio::process::Command;
+
+use crate::{
+    input,
+    recording::{
+        MissingAudio, StartReq, WatermarkPosition, audio_track_playlist, iframe_playlist,
+        segment_dir,
+    },
+};
+
+/// Stands in for the audio of inputs without any, see `MissingAudio`
+const SILENCE_SOURCE: &str = "anullsrc=channel_layout=stereo:sample_rate=48000";
+
+/// Everything needed to (re)build the ffmpeg command line of a recording.
+/// Paths are resolved and validated before the spec is created.
+#[derive(Clone)]
+pub struct CommandSpec {
+    pub req: StartReq,
+    /// `req.input_url` with environment placeholders resolved
+    pub input_url: String,
+    /// Input format (`-f`) for sources that are not URLs, e.g. `lavfi`
+    pub input_format: Option<String>,
+    pub pending_dir: PathBuf,
+    /// Resolved watermark image
+    pub watermark: Option<PathBuf>,
+    /// Named pipe fed from ffmpeg's stdout, see `fifo::relay`
+    pub fifo: Option<PathBuf>,
+    /// Number of audio streams in the input with `preserve_all_audio`;
+    /// every stream after the first gets its own HLS output
+    pub audio_tracks: usize,
+    /// Set when the input was probed without audio, to the requested
+    /// `missing_audio` handling
+    pub no_audio: Option<MissingAudio>,
+}
+
+/// Extra files written next to the HLS output during one ffmpeg run.
+#[derive(Default)]
+pub struct SidecarOutputs {
+    pub audio: Option<PathBuf>,
+    /// Segment list of the WebVTT subtitles
+    pub subtitles: Option<PathBuf>,
+    /// Matroska archive of the whole run
+    pub archive: Option<PathBuf>,
+}
+
+impl CommandSpec {
+    pub fn build(&self, sidecars: &SidecarOutputs) -> Command {
+        let req = &self.req;
+        let playlist = self.pending_dir.join(format!("{}.m3u8", req.name));
+        let seg_pattern = self
+            .segment_dir()
+            .join(format!("{}_seg_%Y-%m-%d_%H-%M-%S_%03d.ts", req.name));
+
+        let mut cmd = Command::new("ffmpeg");
+        cmd.kill_on_drop(true)
+            .stderr(Stdio::piped())
+            .args(&req.global_options)
+            .arg("-y");
+        //cmd.args(["-rtsp_transport", "tcp"]);
+        if self.input_url == input::STDIN {
+            // the producer sets the pace
+            cmd.stdin(Stdio::piped());
+        } else {
+            cmd.arg("-re");
+        }
+        if let Some(pull) = &req.pull {
+            cmd.args(pull.args());
+        }
+        if let Some(format) = &self.input_format {
+            cmd.args(["-f", format]);
+        }
+        if let Some(secs) = req.input_seek_secs {
+            cmd.args(["-ss", &secs.to_string()]);
+        }
+        if let Some(secs) = req.duration_secs {
+            cmd.args(["-t", &secs.to_string()]);
+        }
+        cmd.args(["-i", &self.input_url]);
+        if let Some(image) = &self.watermark {
+            cmd.arg("-i").arg(image);
+        }
+        // silence goes after the watermark, which filters refer to as input 1
+        let silence = (self.no_audio == Some(MissingAudio::Silence)).then(|| {
+            cmd.args(["-f", "lavfi", "-i", SILENCE_SOURCE]);
+            format!("{}:a", 1 + self.watermark.is_some() as usize)
+        });
+
+        match (&req.transcode, &silence) {
+            (None, Some(s)) => {
+                // raw PCM cannot be copied into MPEG-TS
+                cmd.args(["-map", "0:v?"]).args(["-map", s]).args([
+                    "-c:v",
+                    "copy",
+                    "-c:a",
+                    "aac",
+                    "-shortest",
+                ]);
+            }
+            (None, None) => {
+                if req.preserve_all_audio {
+                    cmd.args(["-map", "0:v:0?"]).args(["-map", "0:a:0?"]);
+                } else if sidecars.archive.is_some() {
+                    // the tee muxer selects no streams by itself
+                    cmd.args(["-map", "0:v?"]).args(["-map", "0:a?"]);
+                }
+                cmd.args(["-c", "copy"]);
+            }
+            (Some(t), _) => {
+                if let Some(wm) = &req.watermark {
+                    cmd.args([
+                        "-filter_complex",
+                        &format!("[0:v][1:v]overlay={}[v]", overlay_position(wm.position)),
+                    ])
+                    .args(["-map", "[v]"])
+                    .args([
+                        "-map",
+                        match &silence {
+                            Some(s) => s,
+                            None if req.preserve_all_audio => "0:a:0?",
+                            None => "0:a?",
+                        },
+                    ]);
+                } else if let Some(s) = &silence {
+                    cmd.args(["-map", "0:v?"]).args(["-map", s]);
+                } else if req.preserve_all_audio {
+                    cmd.args(["-map", "0:v:0?"]).args(["-map", "0:a:0?"]);
+                } else if sidecars.archive.is_some() {
+                    cmd.args(["-map", "0:v?"]).args(["-map", "0:a?"]);
+                }
+                if silence.is_some() {
+                    cmd.arg("-shortest");
+                }
+                cmd.args(["-c:v", &t.video_codec])
+                    .args(["-c:a", &t.audio_codec])
+                    // keyframe at every segment boundary
+                    .args([
+                        "-force_key_frames",
+                        &format!("expr:gte(t,n_forced*{})", req.hls_time),
+                    ]);
+                if let Some(b) = &t.video_bitrate {
+                    cmd.args(["-b:v", b]);
+                }
+                if let Some(p) = &t.preset {
+                    cmd.args(["-preset", p]);
+                }
+                if let Some(fps) = req.max_fps {
+                    cmd.args(["-fpsmax", &fps.to_string()]);
+                }
+            }
+        }
+
+        let hls_opts = self.hls_options(&seg_pattern);
+        match &sidecars.archive {
+            None => {
+                cmd.args(["-f", "hls"]);
+                for (key, value) in &hls_opts {
+                    cmd.arg(format!("-{}", key)).arg(value);
+                }
+                cmd.arg(playlist.to_string_lossy().to_string());
+            }
+            Some(mkv) => {
+                // tee keeps the HLS output running if the archive fails
+                let hls: Vec<String> = hls_opts
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, tee_escape(value)))
+                    .collect();
+                cmd.args(["-f", "tee"]).arg(format!(
+                    "[f=hls:{}]{}|[f=matroska:onfail=ignore]{}",
+                    hls.join(":"),
+                    tee_escape(&playlist.to_string_lossy()),
+                    tee_escape(&mkv.to_string_lossy()),
+                ));
+            }
+        }
+
+        for track in 1..self.audio_tracks {
+            let seg_pattern = self.segment_dir().join(format!(
+                "{}_seg_a{}_%Y-%m-%d_%H-%M-%S_%03d.ts",
+                req.name, track
+            ));
+            let codec = req.transcode.as_ref().map_or("copy", |t| &t.audio_codec);
+            cmd.args(["-map", &format!("0:a:{}", track)])
+                .args(["-c:a", codec])
+                .args(["-f", "hls"]);
+            for (key, value) in self.hls_options(&seg_pattern) {
+                cmd.arg(format!("-{}", key)).arg(value);
+            }
+            cmd.arg(audio_track_playlist(&self.pending_dir, &req.name, track));
+        }
+        if req.iframe_playlist {
+            let seg_pattern = self
+                .segment_dir()
+                .join(format!("{}_seg_iframe_%Y-%m-%d_%H-%M-%S_%03d.ts", req.name));
+            // the input video as is; with transcode it is a separate
+            // rendition with the input's keyframes. Audio-only inputs
+            // just get no I-frames.
+            cmd.args(["-map", "0:v:0?"])
+                .args(["-c:v", "copy"])
+                .args(["-f", "hls"]);
+            for (key, mut value) in self.hls_options(&seg_pattern) {
+                if key == "hls_flags" {
+                    value.push_str("+iframes_only");
+                }
+                cmd.arg(format!("-{}", key)).arg(value);
+            }
+            cmd.arg(iframe_playlist(&self.pending_dir, &req.name));
+        }
+        if let Some(audio) = &sidecars.audio {
+            // ADTS only carries AAC; other source codecs cannot be copied
+            cmd.args(["-map", silence.as_deref().unwrap_or("0:a:0")])
+                .args(["-c:a", "aac"])
+                .args(["-f", "adts"])
+                .arg(audio.to_string_lossy().to_string());
+        }
+        if let Some(list) = &sidecars.subtitles {
+            let vtt_pattern = self
+                .pending_dir
+                .join(format!("{}_sub_%Y-%m-%d_%H-%M-%S.vtt", req.name));
+            // inputs without subtitles are recorded all the same
+            cmd.args(["-map", "0:s:0?"])
+                .args(["-c:s", "webvtt"])
+                .args(["-f", "segment"])
+                .args(["-segment_time", &req.hls_time.to_string()])
+                .args(["-segment_format", "webvtt"])
+                .args(["-segment_list_type", "m3u8"])
+                .args(["-segment_list", &list.to_string_lossy()])
+                .args(["-strftime", "1"])
+                .arg(vtt_pattern.to_string_lossy().to_string());
+        }
+        if self.fifo.is_some() {
+            if silence.is_some() {
+                // keep the PCM silence out of the automatic stream selection
+                cmd.args(["-map", "0:v?"]);
+            }
+            cmd.args(["-c", "copy"])
+                .args(["-f", "mpegts"])
+                .arg("pipe:1")
+                .stdout(Stdio::piped());
+        }
+        cmd
+    }
+
+    fn segment_dir(&self) -> PathBuf {
+        if self.req.segment_subdir {
+            segment_dir(&self.pending_dir, &self.req.name)
+        } else {
+            self.pending_dir.clone()
+        }
+    }
+
+    /// Options of the HLS muxer writing segments named after `seg_pattern`.
+    fn hls_options(&self, seg_pattern: &Path) -> Vec<(&'static str, String)> {
+        let mut hls_flags = "append_list+discont_start+program_date_time+temp_file".to_string();
+        let mut hls_opts = vec![("hls_time", self.req.hls_time.to_string())];
+        match self.req.live_window_segments {
+            // sliding window; the archiver keeps segments ffmpeg deletes
+            Some(n) => {
+                hls_opts.push(("hls_list_size", n.to_string()));
+                hls_flags.push_str("+delete_segments");
+            }
+            None => {
+                hls_opts.push(("hls_list_size", "0".to_string()));
+                hls_opts.push(("hls_playlist_type", "event".to_string()));
+            }
+        }
+        if self.req.segment_subdir {
+            // ffmpeg lists segments by file name only
+            hls_opts.push(("hls_base_url", format!("{}/", self.req.name)));
+        }
+        hls_opts.extend([
+            ("hls_flags", hls_flags),
+            ("strftime", "1".to_string()),
+            (
+                "hls_segment_filename",
+                seg_pattern.to_string_lossy().to_string(),
+            ),
+        ]);
+        hls_opts
+    }
+}
+
+/// Escape characters with a meaning in tee muxer output specs.
+fn tee_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ':' | '\'' | '|' | '[' | ']') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn overlay_position(pos: WatermarkPosition) -> &'static str {
+    match pos {
+        WatermarkPosition::TopLeft => "10:10",
+        WatermarkPosition::TopRight => "W-w-10:10",
+        WatermarkPosition::BottomLeft => "10:H-h-10",
+        WatermarkPosition::BottomRight => "W-w-10:H-h-10",
+    }
+}
+
+impl CommandSpec {
+    /// Command line for logging, with the input as the user wrote it so
+    /// resolved secrets are not printed.
+    pub fn display(&self, cmd: &Command) -> String {
+        let line = format_command(cmd);
+        if self.input_url == self.req.input_url {
+            line
+        } else {
+            line.replace(&self.input_url, &self.req.input_url)
+        }
+    }
+}
+
+pub fn format_command(cmd: &Command) -> String {
+    let mut s = String::from("ffmpeg");
+    for arg in cmd.as_std().get_args() {
+        s.push(' ');
+        s.push_str(&arg.to_string_lossy());
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(req: serde_json::Value) -> CommandSpec {
+        let req: StartReq = serde_json::from_value(req).unwrap();
+        CommandSpec {
+            input_url: req.input_url.clone(),
+            req,
+            input_format: None,
+            pending_dir: PathBuf::from("/data/pending_recordings"),
+            watermark: None,
+            fifo: None,
+            audio_tracks: 0,
+            no_audio: None,
+        }
+    }
+
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.as_std()
+            .get_args()
+            .map(|a| a.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn archive_output_maps_streams_for_tee() {
+        let sidecars = SidecarOutputs {
+            archive: Some(PathBuf::from("/data/pending_recordings/cam1.mkv")),
+            ..Default::default()
+        };
+        for req in [
+            serde_json::json!({"name": "cam1", "input_url": "rtmp://example.com/live"}),
+            serde_json::json!({
+                "name": "cam1",
+                "input_url": "rtmp://example.com/live",
+                "transcode": {"video_codec": "libx264", "audio_codec": "aac"},
+            }),
+        ] {
+            let args = args(&spec(req).build(&sidecars));
+            let tee = args.iter().position(|a| a == "tee").unwrap();
+            let maps: Vec<_> = args[..tee]
+                .windows(2)
+                .filter(|w| w[0] == "-map")
+                .map(|w| w[1].as_str())
+                .collect();
+            assert_eq!(maps, ["0:v?", "0:a?"]);
+        }
+    }
+
+    #[test]
+    fn subtitle_output_tolerates_inputs_without_subtitles() {
+        let sidecars = SidecarOutputs {
+            subtitles: Some(PathBuf::from("/data/pending_recordings/cam1.vtt.m3u8")),
+            ..Default::default()
+        };
+        let req = serde_json::json!({"name": "cam1", "input_url": "rtmp://example.com/live"});
+        let args = args(&spec(req).build(&sidecars));
+        assert!(args.windows(2).any(|w| w == ["-map", "0:s:0?"]));
+    }
+
+    #[test]
+    fn iframe_output_tolerates_inputs_without_video() {
+        let req = serde_json::json!({
+            "name": "cam1",
+            "input_url": "rtmp://example.com/live",
+            "iframe_playlist": true,
+        });
+        let args = args(&spec(req).build(&SidecarOutputs::default()));
+        assert!(args.windows(2).any(|w| w == ["-map", "0:v:0?"]));
+    }
+
+    #[test]
+    fn audio_sidecar_is_encoded_to_aac() {
+        let sidecars = SidecarOutputs {
+            audio: Some(PathBuf::from("/data/pending_recordings/cam1.aac")),
+            ..Default::default()
+        };
+        let req = serde_json::json!({"name": "cam1", "input_url": "rtmp://example.com/live"});
+        let args = args(&spec(req).build(&sidecars));
+        let adts = args.iter().position(|a| a == "adts").unwrap();
+        assert_eq!(args[adts - 5..adts - 1], ["-map", "0:a:0", "-c:a", "aac"]);
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{Context, Result};
+use http::HeaderValue;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::recording::StartReq;
+
+pub const DEFAULT_VOD_PLAYLIST: &str = "index.m3u8";
+
+/// Server configuration as read from the JSON config file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Base directory for DVR files (overridden by `--base-dir`)
+    pub base_dir: Option<PathBuf>,
+    /// Directory local file inputs must live in (unrestricted when unset)
+    pub media_root: Option<PathBuf>,
+    /// Directory watermark images are loaded from
+    pub assets_dir: Option<PathBuf>,
+    /// Directory `fifo_output` named pipes are created in
+    pub fifo_dir: Option<PathBuf>,
+    /// File name of the playlist written on finalize
+    pub vod_playlist: String,
+    pub api_addr: SocketAddr,
+    pub vod_addr: SocketAddr,
+    /// Serve the API and VOD routes together on this Unix domain socket
+    /// instead of the two TCP addresses
+    pub bind_uds: Option<PathBuf>,
+    /// Path prefix all routes are served under and generated URLs start
+    /// with, e.g. `/dvr` behind a reverse proxy (empty = served at `/`)
+    pub base_path: String,
+    /// Public URL of the VOD server used in generated playlists, e.g.
+    /// `https://media.example.com`. Defaults to the API request's host
+    /// with the `vod_addr` port.
+    pub vod_base_url: Option<String>,
+    /// Serve an overview page at `/` on the API server
+    pub index_page: bool,
+    /// Isolated namespaces, each with its own directories and recordings
+    /// below `tenants/{name}`, served under `/t/{name}/`
+    pub tenants: Vec<String>,
+    /// Settings that may be changed at runtime via SIGHUP
+    #[serde(flatten)]
+    pub settings: Settings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            base_dir: None,
+            media_root: None,
+            assets_dir: None,
+            fifo_dir: None,
+            vod_playlist: DEFAULT_VOD_PLAYLIST.to_string(),
+            api_addr: ([0, 0, 0, 0], 8080).into(),
+            vod_addr: ([0, 0, 0, 0], 8081).into(),
+            bind_uds: None,
+            base_path: String::new(),
+            vod_base_url: None,
+            index_page: true,
+            tenants: Vec::new(),
+            settings: Settings::default(),
+        }
+    }
+}
+
+/// Hot-reloadable settings.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Maximum number of recordings running at the same time (0 = unlimited);
+    /// recordings resumed at startup always start
+    pub max_concurrent: usize,
+    /// Origins allowed by CORS; empty allows any origin
+    pub cors_origins: Vec<String>,
+    /// Extensions cleanup may delete from recording directories besides
+    /// the files the server writes itself (`ARTIFACT_EXTENSIONS`); anything
+    /// else is left alone
+    pub cleanup_extensions: Vec<String>,
+    /// Applied to `/api/start` requests for fields they leave out; any
+    /// `StartReq` recording option can be given (not `START_CONTROL_FIELDS`)
+    pub default_start: serde_json::Map<String, serde_json::Value>,
+    /// How long ffprobe results are reused (0 = no caching)
+    pub probe_cache_secs: u64,
+    /// Warn when a test write to the pending directory takes longer than
+    /// this (0 = never warn)
+    pub disk_latency_warn_ms: u64,
+    /// Seconds finalize keeps the live playlist and segments around (as
+    /// copies) so connected players can finish (0 = move right away)
+    pub finalize_grace_secs: u64,
+    /// Segments finalize moves at the same time (1 = one after another)
+    pub finalize_concurrency: usize,
+    /// Backoff for sources that keep failing
+    pub restart_cooldown: RestartCooldown,
+    /// Delete finished recordings this many days after finalize, unless
+    /// they set their own `retention_days` (0 = keep forever)
+    pub retention_days: u32,
+    /// Rotate a recording's ffmpeg log once it reaches this size
+    /// (0 = never rotate)
+    pub log_max_bytes: u64,
+    /// Rotated logs kept per recording (`{name}.log.1`, ...)
+    pub log_keep: usize,
+}
+
+/// After more than `max_restarts` ffmpeg restarts within `window_secs`,
+/// wait `cooldown_secs` before the next one instead of a few seconds.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestartCooldown {
+    /// 0 disables the cooldown
+    pub max_restarts: usize,
+    pub window_secs: u64,
+    pub cooldown_secs: u64,
+}
+
+impl Default for RestartCooldown {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            window_secs: 60,
+            cooldown_secs: 300,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 0,
+            cors_origins: Vec::new(),
+            cleanup_extensions: Vec::new(),
+            default_start: serde_json::Map::new(),
+            probe_cache_secs: 30,
+            disk_latency_warn_ms: 500,
+            finalize_grace_secs: 0,
+            finalize_concurrency: 1,
+            restart_cooldown: RestartCooldown::default(),
+            retention_days: 0,
+            log_max_bytes: 10 * 1024 * 1024,
+            log_keep: 2,
+        }
+    }
+}
+
+/// Extensions of the files recordings consist of: segments, playlists,
+/// subtitles, audio and archive sidecars, metadata and logs. Cleanup may
+/// always delete these.
+pub const ARTIFACT_EXTENSIONS: &[&str] = &[
+    "ts", "m4s", "m3u8", "mpd", "vtt", "aac", "mkv", "json", "log",
+];
+
+impl Settings {
+    /// Whether cleanup is allowed to delete `path`, judged by its extension.
+    pub fn may_remove(&self, path: &Path) -> bool {
+        path.extension().is_some_and(|ext| {
+            ARTIFACT_EXTENSIONS
+                .iter()
+                .copied()
+                .chain(self.cleanup_extensions.iter().map(String::as_str))
+                .any(|e| ext.eq_ignore_ascii_case(e.trim_start_matches('.')))
+        })
+    }
+
+    pub fn allows_origin(&self, origin: &HeaderValue) -> bool {
+        self.cors_origins.is_empty()
+            || self
+                .cors_origins
+                .iter()
+                .any(|o| o == "*" || o.as_bytes() == origin.as_bytes())
+    }
+}
+
+impl Config {
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        let mut config: Config = serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse config file {}", path.display()))?;
+        crate::recording::sanitize_playlist_name(&config.vod_playlist)?;
+        check_default_start(&config.settings.default_start)?;
+        config.base_path = normalize_base_path(&config.base_path)?;
+        Ok(config)
+    }
+}
+
+/// `StartReq` fields that decide how one start request is handled rather
+/// than how the recording is made; `default_start` cannot set them.
+pub const START_CONTROL_FIELDS: &[&str] = &[
+    "name",
+    "resume",
+    "queue_if_full",
+    "priority",
+    "wait_for_first_segment",
+];
+
+/// `default_start` values must have the types `StartReq` expects, so a bad
+/// default fails at startup and not with every start request.
+fn check_default_start(defaults: &serde_json::Map<String, serde_json::Value>) -> Result<()> {
+    if let Some(field) = START_CONTROL_FIELDS
+        .iter()
+        .find(|f| defaults.contains_key(**f))
+    {
+        anyhow::bail!("invalid default_start: '{}' cannot be defaulted", field);
+    }
+    let mut req = defaults.clone();
+    req.entry("name").or_insert("default".into());
+    req.entry("input_url").or_insert("default".into());
+    serde_json::from_value::<StartReq>(req.into()).context("invalid default_start")?;
+    Ok(())
+}
+
+/// `"dvr/"` -> `"/dvr"`; `"/"` -> `""`.
+fn normalize_base_path(path: &str) -> Result<String> {
+    let trimmed = path.trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    if !trimmed.split('/').all(|part| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    }) {
+        anyhow::bail!("invalid base_path: {}", path);
+    }
+    Ok(format!("/{}", trimmed))
+}
+
+/// Shared handle to the current settings. Readers get a cheap snapshot,
+/// a reload swaps the whole value at once.
+pub struct SettingsHandle(RwLock<Arc<Settings>>);
+
+impl SettingsHandle {
+    pub fn new(settings: Settings) -> Self {
+        Self(RwLock::new(Arc::new(settings)))
+    }
+
+    pub fn load(&self) -> Arc<Settings> {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn store(&self, settings: Settings) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(settings);
+    }
+}
+
+/// Re-read the config file on SIGHUP and swap the hot-reloadable settings.
+/// Changes to addresses and directories only take effect after a restart.
+#[cfg(unix)]
+pub fn spawn_reload_on_sighup(path: PathBuf, running: Config, handle: Arc<SettingsHandle>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    tokio::spawn(async move {
+        let mut hup = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                error!(error=?e, "failed to install SIGHUP handler");
+                return;
+            }
+        };
+        while hup.recv().await.is_some() {
+            info!(config=?path, "SIGHUP received - reloading config");
+            let new = match Config::load(&path).await {
+                Ok(c) => c,
+                Err(e) => {
+                    error!(error=?e, "config reload failed, keeping current settings");
+                    continue;
+                }
+            };
+            if new.base_dir != running.base_dir
+                || new.media_root != running.media_root
+                || new.assets_dir != running.assets_dir
+                || new.fifo_dir != running.fifo_dir
+            {
+                info!("directories changed - ignored until restart");
+            }
+            if new.vod_playlist != running.vod_playlist {
+                info!("vod_playlist changed - ignored until restart");
+            }
+            if new.vod_base_url != running.vod_base_url || new.base_path != running.base_path {
+                info!("URL settings changed - ignored until restart");
+            }
+            if new.api_addr != running.api_addr
+                || new.vod_addr != running.vod_addr
+                || new.bind_uds != running.bind_uds
+            {
+                info!("bind addresses changed - ignored until restart");
+            }
+            handle.store(new.settings);
+            info!("config reloaded");
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_reload_on_sighup(_path: PathBuf, _running: Config, _handle: Arc<SettingsHandle>) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[tokio::test]
+    async fn vod_playlist_must_not_shadow_side_playlists() {
+        let dir = TempDir::new();
+        let path = dir.path().join("config.json");
+        for name in ["master.m3u8", "subtitles.m3u8", "iframes.m3u8", "index"] {
+            std::fs::write(&path, format!(r#"{{"vod_playlist":"{}"}}"#, name)).unwrap();
+            assert!(Config::load(&path).await.is_err(), "{name}");
+        }
+        std::fs::write(&path, r#"{"vod_playlist":"vod.m3u8"}"#).unwrap();
+        assert_eq!(Config::load(&path).await.unwrap().vod_playlist, "vod.m3u8");
+    }
+
+    #[tokio::test]
+    async fn default_start_is_type_checked() {
+        let dir = TempDir::new();
+        let path = dir.path().join("config.json");
+        std::fs::write(&path, r#"{"default_start":{"hls_time":"4"}}"#).unwrap();
+        assert!(Config::load(&path).await.is_err());
+        std::fs::write(&path, r#"{"default_start":{"resume":true}}"#).unwrap();
+        assert!(Config::load(&path).await.is_err());
+        std::fs::write(&path, r#"{"default_start":{"auto_finalize":true}}"#).unwrap();
+        assert!(Config::load(&path).await.is_ok());
+    }
+
+    #[test]
+    fn cleanup_allowlist_only_limits_unknown_files() {
+        let settings = Settings {
+            cleanup_extensions: vec![".jpg".into()],
+            ..Default::default()
+        };
+        for file in [
+            "cam1.aac",
+            "cam1.mkv",
+            "cam1.meta.json",
+            "cam1.ts",
+            "thumb.JPG",
+        ] {
+            assert!(settings.may_remove(Path::new(file)), "{file}");
+        }
+        for file in ["notes.txt", "README"] {
+            assert!(!settings.may_remove(Path::new(file)), "{file}");
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+
+use anyhow::Result;
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    time::{Duration, interval},
+};
+use tracing::{debug, error, warn};
+
+use crate::config::SettingsHandle;
+
+/// How often a test write is timed.
+const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+/// Small enough not to matter, large enough to reach the disk.
+const PROBE_BYTES: usize = 64 * 1024;
+const PROBE_FILE: &str = ".write-latency-probe";
+
+/// Write latency of the pending directory, as measured by `spawn`.
+pub struct DiskMonitor {
+    /// Latest measurement, `u64::MAX` until the first one
+    last_ms: AtomicU64,
+    /// Measurements above `disk_latency_warn_ms`
+    slow_writes: AtomicU64,
+}
+
+impl DiskMonitor {
+    pub fn new() -> Self {
+        Self {
+            last_ms: AtomicU64::new(u64::MAX),
+            slow_writes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn last_ms(&self) -> Option<u64> {
+        Some(self.last_ms.load(Ordering::Relaxed)).filter(|ms| *ms != u64::MAX)
+    }
+
+    pub fn slow_writes(&self) -> u64 {
+        self.slow_writes.load(Ordering::Relaxed)
+    }
+
+    /// Periodically time a synced write to `dir` and warn when it takes
+    /// longer than the configured threshold. Stalling disks make ffmpeg fall
+    /// behind long before they fail outright.
+    pub fn spawn(self: &Arc<Self>, dir: PathBuf, settings: Arc<SettingsHandle>) {
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let mut tick = interval(PROBE_INTERVAL);
+            loop {
+                tick.tick().await;
+                let elapsed = match time_write(&dir).await {
+                    Ok(d) => d,
+                    Err(e) => {
+                        error!(dir=?dir, error=?e, "disk latency probe failed");
+                        continue;
+                    }
+                };
+                let ms = elapsed.as_millis() as u64;
+                monitor.last_ms.store(ms, Ordering::Relaxed);
+
+                let threshold = settings.load().disk_latency_warn_ms;
+                if threshold > 0 && ms > threshold {
+                    monitor.slow_writes.fetch_add(1, Ordering::Relaxed);
+                    warn!(dir=?dir, latency_ms = ms, threshold_ms = threshold, "slow disk write");
+                } else {
+                    debug!(dir=?dir, latency_ms = ms, "disk latency probe");
+                }
+            }
+        });
+    }
+}
+
+async fn time_write(dir: &std::path::Path) -> Result<Duration> {
+    let path = dir.join(PROBE_FILE);
+    let data = vec![0u8; PROBE_BYTES];
+    let start = Instant::now();
+    let mut f = fs::File::create(&path).await?;
+    f.write_all(&data).await?;
+    f.sync_all().await?;
+    let elapsed = start.elapsed();
+    drop(f);
+    fs::remove_file(&path).await.ok();
+    Ok(elapsed)
+}
@@ -0,0 +1,121 @@
+//! Per-segment events for external indexing.
+//!
+//! While a recording runs, a watcher polls its live playlist and publishes
+//! every segment ffmpeg completes (it is only listed after the temp file
+//! has been renamed) on a broadcast channel. `/api/events/segments`
+//! streams them as server-sent events.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tokio::{
+    fs,
+    sync::{broadcast, oneshot},
+    time::{Duration, interval},
+};
+
+use crate::playlist::{parse_segments, uri_path};
+
+/// Same cadence as the archiver; a segment is reported well within its
+/// own duration.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Events buffered per subscriber before slow ones start missing some
+pub const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SegmentEvent {
+    /// Recording name
+    pub name: String,
+    /// Segment URI as listed in the live playlist
+    pub file: String,
+    pub size: u64,
+    pub duration: f64,
+    /// Wall-clock time of the segment start, from the playlist
+    pub program_date_time: Option<String>,
+}
+
+struct Watcher {
+    playlist: PathBuf,
+    pending_dir: PathBuf,
+    name: String,
+    seen: HashSet<String>,
+    events: broadcast::Sender<SegmentEvent>,
+}
+
+impl Watcher {
+    /// Segments already listed (a resumed recording) are not reported again.
+    async fn open(pending_dir: &Path, name: &str, events: broadcast::Sender<SegmentEvent>) -> Self {
+        let playlist = pending_dir.join(format!("{}.m3u8", name));
+        let existing = fs::read_to_string(&playlist).await.unwrap_or_default();
+        Self {
+            seen: parse_segments(&existing)
+                .into_iter()
+                .map(|s| s.uri)
+                .collect(),
+            playlist,
+            pending_dir: pending_dir.to_path_buf(),
+            name: name.to_string(),
+            events,
+        }
+    }
+
+    async fn poll(&mut self) {
+        let Ok(content) = fs::read_to_string(&self.playlist).await else {
+            return;
+        };
+        for seg in parse_segments(&content) {
+            if !self.seen.insert(seg.uri.clone()) {
+                continue;
+            }
+            let size = fs::metadata(self.pending_dir.join(uri_path(&seg.uri)))
+                .await
+                .map_or(0, |m| m.len());
+            // no subscribers is fine
+            let _ = self.events.send(SegmentEvent {
+                name: self.name.clone(),
+                file: seg.uri,
+                size,
+                duration: seg.duration,
+                program_date_time: seg.program_date_time,
+            });
+        }
+    }
+}
+
+/// Report new segments of `name` until the handle is finished.
+pub async fn spawn(
+    pending_dir: &Path,
+    name: &str,
+    events: broadcast::Sender<SegmentEvent>,
+) -> WatcherHandle {
+    let mut watcher = Watcher::open(pending_dir, name, events).await;
+    let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+    let task = tokio::spawn(async move {
+        let mut tick = interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = tick.tick() => watcher.poll().await,
+                _ = &mut stop_rx => break,
+            }
+        }
+        // segments written since the last poll
+        watcher.poll().await;
+    });
+    WatcherHandle { stop_tx, task }
+}
+
+pub struct WatcherHandle {
+    stop_tx: oneshot::Sender<()>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl WatcherHandle {
+    /// Report the last segments, then stop.
+    pub async fn finish(self) {
+        let _ = self.stop_tx.send(());
+        let _ = self.task.await;
+    }
+}
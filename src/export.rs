@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+};
+use tracing::{error, info};
+
+use crate::{
+    command::format_command,
+    jobs::{Cancelled, JobGuard, JobKind},
+    playlist::parse_segments,
+    recording::{check_option_value, sanitize_name},
+    state::AppState,
+};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExportReq {
+    /// `copy` (default) or an encoder such as `libx264`
+    #[serde(default = "default_codec")]
+    pub video_codec: String,
+    /// `copy` (default) or an encoder such as `aac`
+    #[serde(default = "default_codec")]
+    pub audio_codec: String,
+}
+
+impl Default for ExportReq {
+    fn default() -> Self {
+        Self {
+            video_codec: default_codec(),
+            audio_codec: default_codec(),
+        }
+    }
+}
+
+fn default_codec() -> String {
+    "copy".to_string()
+}
+
+/// Start converting a finished recording to `{name}/{name}.mp4` in the
+/// background. Returns the job id.
+pub async fn start_export(state: &AppState, name: &str, req: ExportReq) -> Result<u64> {
+    let name = sanitize_name(name)?;
+    check_option_value("video_codec", &req.video_codec)?;
+    check_option_value("audio_codec", &req.audio_codec)?;
+
+    let Some(playlist) = state.find_vod_playlist(&name).await else {
+        anyhow::bail!("Recording '{}' is not finalized", name);
+    };
+    let dir = state.finished_dir.join(&name);
+    let input = dir.join(playlist);
+    let content = fs::read_to_string(&input).await?;
+    let total_secs: f64 = parse_segments(&content).iter().map(|s| s.duration).sum();
+
+    let job = state.jobs.register(JobKind::Export, &name).await;
+    let id = job.id;
+    let part = dir.join(format!("{}.mp4.part", name));
+    let out = dir.join(format!("{}.mp4", name));
+
+    // the playlist as input keeps playlist order, whatever the file names
+    let mut cmd = Command::new("ffmpeg");
+    cmd.kill_on_drop(true)
+        .args(["-y", "-v", "error", "-nostats"])
+        .args(["-progress", "pipe:1"])
+        .arg("-i")
+        .arg(&input)
+        .args(["-c:v", &req.video_codec])
+        .args(["-c:a", &req.audio_codec])
+        .args(["-movflags", "+faststart"])
+        .args(["-f", "mp4"])
+        .arg(&part)
+        .stdout(std::process::Stdio::piped());
+    info!(%name, job=id, "Starting export: {}", format_command(&cmd));
+    let child = cmd.spawn().context("ffmpeg could not be started")?;
+
+    tokio::spawn(async move {
+        let result = match run_export(child, &job, total_secs).await {
+            Ok(true) => match fs::rename(&part, &out).await {
+                Ok(()) => {
+                    info!(%name, file=?out, "export finished");
+                    Ok(())
+                }
+                Err(e) => {
+                    error!(%name, error=?e, "failed to move export into place");
+                    Err(anyhow::Error::from(e).context("failed to move export into place"))
+                }
+            },
+            Ok(false) => {
+                info!(%name, "export cancelled");
+                fs::remove_file(&part).await.ok();
+                Err(Cancelled.into())
+            }
+            Err(e) => {
+                error!(%name, error=?e, "export failed");
+                fs::remove_file(&part).await.ok();
+                Err(e)
+            }
+        };
+        job.finish(&result);
+    });
+    Ok(id)
+}
+
+/// Wait for ffmpeg while feeding its `-progress` output into the job.
+/// Returns `Ok(false)` if the job was cancelled.
+async fn run_export(
+    mut child: tokio::process::Child,
+    job: &JobGuard,
+    total_secs: f64,
+) -> Result<bool> {
+    let stdout = child.stdout.take().context("ffmpeg stdout not captured")?;
+    let mut lines = BufReader::new(stdout).lines();
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                match line? {
+                    Some(l) => {
+                        if let Some(us) = l.strip_prefix("out_time_us=")
+                            && let Ok(us) = us.trim().parse::<f64>()
+                            && total_secs > 0.0
+                        {
+                            job.set_progress(us / 1_000_000.0 / total_secs).await;
+                        }
+                    }
+                    None => break,
+                }
+            }
+            _ = job.cancel.cancelled() => {
+                let _ = child.start_kill();
+                let _ = child.wait().await;
+                return Ok(false);
+            }
+        }
+    }
+    let status = child.wait().await?;
+    if !status.success() {
+        anyhow::bail!("ffmpeg exited with {}", status);
+    }
+    job.set_progress(1.0).await;
+    Ok(true)
+}
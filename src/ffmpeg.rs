@@ -1,5 +1,15 @@
+use std::{collections::VecDeque, path::Path, time::Duration};
+
 use anyhow::{Context, Result};
-use tokio::process::Command;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::{ChildStderr, Command},
+};
+
+use crate::{input, reclog::RecordingLog};
+
+/// Lines of ffmpeg's stderr kept for classifying failures
+const STDERR_TAIL_LINES: usize = 50;
 
 fn has_word(output: &str, word: &str) -> bool {
     output
@@ -47,3 +57,115 @@ pub async fn check_ffmpeg() -> Result<()> {
     }
     Ok(())
 }
+
+/// Grab a JPEG of (roughly) the last frame of a media file.
+pub async fn extract_last_frame(input: &Path) -> Result<Vec<u8>> {
+    let out = Command::new("ffmpeg")
+        .args(["-v", "error"])
+        .args(["-sseof", "-1"])
+        .arg("-i")
+        .arg(input)
+        .args(["-frames:v", "1"])
+        .args(["-q:v", "3"])
+        .args(["-f", "image2pipe", "-c:v", "mjpeg", "pipe:1"])
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("failed to run ffmpeg for snapshot")?;
+    if !out.status.success() || out.stdout.is_empty() {
+        anyhow::bail!(
+            "ffmpeg snapshot failed with status {}: {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+    Ok(out.stdout)
+}
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Stream and container information of an input as reported by
+/// `ffprobe -show_streams -show_format`.
+pub async fn ffprobe(input_url: &str) -> Result<serde_json::Value> {
+    let run = Command::new("ffprobe")
+        .args(["-v", "error"])
+        .args(["-print_format", "json"])
+        .args(["-show_streams", "-show_format"])
+        .arg(input_url)
+        .kill_on_drop(true)
+        .output();
+    // unreachable network sources would otherwise hang the request
+    let out = tokio::time::timeout(PROBE_TIMEOUT, run)
+        .await
+        .context("ffprobe timed out")?
+        .context("failed to run ffprobe")?;
+    if !out.status.success() {
+        anyhow::bail!(
+            "ffprobe failed with status {}: {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    serde_json::from_slice(&out.stdout).context("ffprobe returned invalid JSON")
+}
+
+/// Pass ffmpeg's stderr through to ours and the recording's log and return
+/// its last lines once ffmpeg closes it. Output is handled line by line so
+/// `secrets` (see `input::secrets`) can be redacted everywhere.
+pub async fn tee_stderr(
+    mut stderr: ChildStderr,
+    log: RecordingLog,
+    secrets: Vec<String>,
+) -> Vec<String> {
+    let mut out = tokio::io::stderr();
+    let mut buf = vec![0u8; 8192];
+    let mut partial = String::new();
+    let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+    let mut emit = async |raw: &str, tail: &mut VecDeque<String>| {
+        let text = input::redact(raw, &secrets);
+        let _ = out.write_all(text.as_bytes()).await;
+        log.write(text.as_bytes()).await;
+        let line = text.trim();
+        if line.is_empty() {
+            return;
+        }
+        if tail.len() == STDERR_TAIL_LINES {
+            tail.pop_front();
+        }
+        tail.push_back(line.to_string());
+    };
+    loop {
+        let n = match stderr.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        partial.push_str(&String::from_utf8_lossy(&buf[..n]));
+        // progress updates end in \r instead of \n
+        while let Some(end) = partial.find(['\n', '\r']) {
+            let raw: String = partial.drain(..=end).collect();
+            emit(&raw, &mut tail).await;
+        }
+    }
+    if !partial.is_empty() {
+        emit(&partial, &mut tail).await;
+    }
+    tail.into()
+}
+
+/// Whether ffmpeg failed because a copied stream cannot go into the output
+/// container, i.e. transcoding would help.
+pub fn is_codec_incompatibility(stderr: &[String]) -> bool {
+    const PATTERNS: &[&str] = &[
+        "not currently supported in container",
+        "could not find tag for codec",
+        "codec not supported",
+        "unsupported codec",
+        "incompatible with output codec",
+        "bitstream malformed",
+        "codec frame size is not set",
+    ];
+    stderr.iter().any(|line| {
+        let line = line.to_ascii_lowercase();
+        PATTERNS.iter().any(|p| line.contains(p))
+    })
+}
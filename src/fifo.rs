@@ -0,0 +1,165 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tokio::{fs, io::AsyncRead};
+
+use crate::state::AppState;
+
+/// Resolve the file name `path` inside the configured `fifo_dir` and create
+/// the FIFO if it does not exist yet.
+pub async fn prepare(state: &AppState, path: &Path) -> Result<PathBuf> {
+    let Some(dir) = &state.config.fifo_dir else {
+        anyhow::bail!("fifo_output requires a fifo_dir to be configured");
+    };
+    let base = fs::canonicalize(dir)
+        .await
+        .with_context(|| format!("failed to canonicalize fifo dir {}", dir.display()))?;
+    let mut parts = path.components();
+    let (Some(Component::Normal(file_name)), None) = (parts.next(), parts.next()) else {
+        anyhow::bail!("fifo_output must be a file name: {}", path.display());
+    };
+    let fifo = base.join(file_name);
+
+    match fs::symlink_metadata(&fifo).await {
+        Ok(md) if is_fifo(&md) => {}
+        Ok(_) => anyhow::bail!("fifo_output {} exists and is not a FIFO", fifo.display()),
+        Err(_) => create(&fifo).await?,
+    }
+    Ok(fifo)
+}
+
+#[cfg(unix)]
+fn is_fifo(md: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    md.file_type().is_fifo()
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_md: &std::fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(unix)]
+async fn create(fifo: &Path) -> Result<()> {
+    let status = tokio::process::Command::new("mkfifo")
+        .arg(fifo)
+        .status()
+        .await
+        .context("failed to run mkfifo")?;
+    if !status.success() {
+        anyhow::bail!("mkfifo {} failed", fifo.display());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn create(_fifo: &Path) -> Result<()> {
+    anyhow::bail!("fifo_output is only supported on Unix")
+}
+
+#[cfg(unix)]
+/// MPEG-TS packet size; a reader that gets part of a packet loses sync.
+const TS_PACKET: u64 = 188;
+
+/// Copy ffmpeg's MPEG-TS output into the FIFO until `input` ends.
+///
+/// ffmpeg never writes to the FIFO itself: opening a FIFO without a reader
+/// blocks, and a stalled reader would stall the recording. Data is dropped
+/// while no reader is attached or the reader cannot keep up, in whole
+/// packets so the reader stays in sync.
+#[cfg(unix)]
+pub async fn relay(mut input: impl AsyncRead + Unpin, fifo: PathBuf) {
+    use tokio::{io::AsyncReadExt, net::unix::pipe};
+    use tracing::{debug, info};
+
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut sender: Option<pipe::Sender> = None;
+    // bytes read from `input` so far; `unsent` are the last of them
+    let mut pos = 0u64;
+    let mut unsent = Vec::new();
+    loop {
+        let n = match input.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        let mut chunk = &buf[..n];
+        if sender.is_none() {
+            // fails with ENXIO while nobody has the FIFO open for reading
+            sender = pipe::OpenOptions::new().open_sender(&fifo).ok();
+            if sender.is_some() {
+                info!(fifo=?fifo, "FIFO reader attached");
+                // the reader starts at a packet boundary
+                chunk = &chunk[(packet_rest(pos) as usize).min(n)..];
+            }
+        }
+        pos += n as u64;
+        let Some(tx) = &sender else {
+            continue;
+        };
+        unsent.extend_from_slice(chunk);
+        while !unsent.is_empty() {
+            match tx.try_write(&unsent) {
+                Ok(w) => {
+                    unsent.drain(..w);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    let dropped = drop_packets(&mut unsent, pos);
+                    debug!(fifo=?fifo, dropped, "FIFO reader too slow");
+                    break;
+                }
+                Err(_) => {
+                    info!(fifo=?fifo, "FIFO reader detached");
+                    sender = None;
+                    unsent.clear();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+/// Bytes from `pos` to the next packet boundary.
+fn packet_rest(pos: u64) -> u64 {
+    (TS_PACKET - pos % TS_PACKET) % TS_PACKET
+}
+
+#[cfg(unix)]
+/// Drop the whole packets of `unsent`, the output up to `end` the reader
+/// has not got yet. What is left finishes the packet the reader is in and
+/// starts the one `end` is in. Returns the number of bytes dropped.
+fn drop_packets(unsent: &mut Vec<u8>, end: u64) -> usize {
+    let start = end - unsent.len() as u64;
+    let head = (packet_rest(start) as usize).min(unsent.len());
+    let tail = (end % TS_PACKET) as usize;
+    if head + tail >= unsent.len() {
+        return 0;
+    }
+    let range = head..unsent.len() - tail;
+    let dropped = range.len();
+    unsent.drain(range);
+    dropped
+}
+
+#[cfg(not(unix))]
+pub async fn relay(_input: impl AsyncRead + Unpin, _fifo: PathBuf) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_whole_packets_are_dropped() {
+        // the reader got 100 bytes of the first packet; 600 bytes were read
+        let mut unsent: Vec<u8> = (100..600).map(|i| (i / 188) as u8).collect();
+        assert_eq!(drop_packets(&mut unsent, 600), 500 - 88 - 36);
+        // rest of packet 0, then the start of packet 3
+        assert_eq!(unsent.len(), 88 + 36);
+        assert!(unsent[..88].iter().all(|&p| p == 0));
+        assert!(unsent[88..].iter().all(|&p| p == 3));
+
+        let mut short = vec![0u8; 50];
+        assert_eq!(drop_packets(&mut short, 150), 0);
+        assert_eq!(short.len(), 50);
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Serialize;
+use tokio::fs;
+
+use super::{ListItem, list_finished::finished_items};
+use crate::{playlist::parse_segments, recording::sanitize_name, state::AppState};
+
+#[derive(Serialize)]
+pub struct ChannelSummary {
+    pub channel: String,
+    pub recordings: usize,
+    /// Sum of the VOD playlist durations in seconds
+    pub total_duration: f64,
+}
+
+/// `GET /api/channels` - finished recordings aggregated by channel.
+pub async fn list_channels(State(state): State<AppState>) -> Json<Vec<ChannelSummary>> {
+    let mut channels: BTreeMap<String, ChannelSummary> = BTreeMap::new();
+    for item in finished_items(&state).await {
+        let Some(channel) = item.channel else {
+            continue;
+        };
+        let duration = vod_duration(&state, &item.name).await;
+        let summary = channels
+            .entry(channel.clone())
+            .or_insert_with(|| ChannelSummary {
+                channel,
+                recordings: 0,
+                total_duration: 0.0,
+            });
+        summary.recordings += 1;
+        summary.total_duration += duration;
+    }
+    Json(channels.into_values().collect())
+}
+
+/// `GET /api/channels/{channel}` - finished recordings of one channel.
+pub async fn channel_recordings(
+    State(state): State<AppState>,
+    Path(raw_channel): Path<String>,
+) -> impl IntoResponse {
+    let channel = match sanitize_name(&raw_channel) {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let items: Vec<ListItem> = finished_items(&state)
+        .await
+        .into_iter()
+        .filter(|item| item.channel.as_deref() == Some(channel.as_str()))
+        .collect();
+    if items.is_empty() {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("No recordings in channel '{}'", channel),
+        )
+            .into_response();
+    }
+    Json(items).into_response()
+}
+
+async fn vod_duration(state: &AppState, name: &str) -> f64 {
+    let Some(file) = state.find_vod_playlist(name).await else {
+        return 0.0;
+    };
+    match fs::read_to_string(state.finished_dir.join(name).join(file)).await {
+        Ok(content) => parse_segments(&content).iter().map(|s| s.duration).sum(),
+        Err(_) => 0.0,
+    }
+}
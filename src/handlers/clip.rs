@@ -0,0 +1,71 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use tokio::fs;
+
+use super::common::vod_origin;
+use crate::{
+    playlist::{clip_playlist, parse_segments},
+    recording::sanitize_name,
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+pub struct ClipQuery {
+    /// Offset into the recording in seconds
+    pub start: f64,
+    /// Length of the clip in seconds
+    pub duration: f64,
+}
+
+/// `GET /api/clip/{name}.m3u8?start=600&duration=120` - playlist for a part
+/// of a finished recording, pointing at its existing VOD segments.
+pub async fn clip(
+    State(state): State<AppState>,
+    Path(file): Path<String>,
+    Query(q): Query<ClipQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let Some(raw_name) = file.strip_suffix(".m3u8") else {
+        return (StatusCode::NOT_FOUND, "expected {name}.m3u8").into_response();
+    };
+    let name = match sanitize_name(raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if !(q.start >= 0.0 && q.duration > 0.0) {
+        return (StatusCode::BAD_REQUEST, "invalid start or duration").into_response();
+    }
+    let Some(playlist) = state.find_vod_playlist(&name).await else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Recording '{}' is not finalized", name),
+        )
+            .into_response();
+    };
+    let content = match fs::read_to_string(state.finished_dir.join(&name).join(playlist)).await {
+        Ok(c) => c,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+
+    let base_url = format!(
+        "{}{}",
+        vod_origin(&state, &headers),
+        state.url(&format!("/vod/{}", name))
+    );
+    match clip_playlist(&parse_segments(&content), q.start, q.duration, &base_url) {
+        Some(body) => (
+            [(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")],
+            body,
+        )
+            .into_response(),
+        None => (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            "clip window is outside the recording",
+        )
+            .into_response(),
+    }
+}
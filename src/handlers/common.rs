@@ -1,7 +1,54 @@
+use axum::{
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
 use serde::Serialize;
 
+use crate::state::AppState;
+
 #[derive(Serialize)]
 pub struct ListItem {
     pub name: String,
     pub playlist: String,
+    pub tags: Vec<String>,
+    pub channel: Option<String>,
+    /// URLs of extracted audio files
+    pub audio: Vec<String>,
+    /// URL of the master playlist that adds subtitles, if any
+    pub master: Option<String>,
+    /// Total length in seconds, known once the recording is finalized
+    pub duration_secs: Option<f64>,
+}
+
+/// Where clients reach the VOD server: the configured URL, or the host
+/// they used for this request with the VOD port (the same origin when both
+/// are served on one Unix socket).
+pub fn vod_origin(state: &AppState, headers: &HeaderMap) -> String {
+    if let Some(url) = &state.config.vod_base_url {
+        return url.trim_end_matches('/').to_string();
+    }
+    let host = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost");
+    if state.config.bind_uds.is_some() {
+        return format!("http://{}", host);
+    }
+    // strip the API port, keeping bracketed IPv6 addresses intact
+    let host = match host.rsplit_once(':') {
+        Some((h, port)) if !port.contains(']') => h,
+        _ => host,
+    };
+    format!("http://{}:{}", host, state.config.vod_addr.port())
+}
+
+/// 503 for requests that would start a recording in maintenance mode.
+pub fn refuse_in_maintenance(state: &AppState) -> Option<Response> {
+    state.in_maintenance().then(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Maintenance mode: no new recordings are started",
+        )
+            .into_response()
+    })
 }
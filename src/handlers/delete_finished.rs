@@ -0,0 +1,94 @@
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{
+    retention::{self, DAY},
+    state::{AppState, FinishedEntry},
+};
+
+#[derive(Deserialize)]
+pub struct DeleteFinishedReq {
+    /// Must be `true`, guards against accidental calls
+    #[serde(default)]
+    pub confirm: bool,
+    /// Finalized at least this many days ago
+    pub older_than_days: Option<u32>,
+    pub tag: Option<String>,
+    pub channel: Option<String>,
+    pub name_prefix: Option<String>,
+}
+
+impl DeleteFinishedReq {
+    fn has_filter(&self) -> bool {
+        self.older_than_days.is_some()
+            || self.tag.is_some()
+            || self.channel.is_some()
+            || self.name_prefix.is_some()
+    }
+
+    async fn matches(&self, state: &AppState, entry: &FinishedEntry) -> bool {
+        if let Some(tag) = &self.tag
+            && !entry.meta.tags.contains(tag)
+        {
+            return false;
+        }
+        if let Some(channel) = &self.channel
+            && entry.meta.channel.as_ref() != Some(channel)
+        {
+            return false;
+        }
+        if let Some(prefix) = &self.name_prefix
+            && !entry.name.starts_with(prefix.as_str())
+        {
+            return false;
+        }
+        if let Some(days) = self.older_than_days {
+            return retention::age(state, entry)
+                .await
+                .is_some_and(|age| age >= DAY * days);
+        }
+        true
+    }
+}
+
+/// `POST /api/finished/delete` - delete every finished recording matching
+/// all given filters. Recordings running again under the same name are
+/// left alone.
+pub async fn delete_finished(
+    State(state): State<AppState>,
+    Json(req): Json<DeleteFinishedReq>,
+) -> impl IntoResponse {
+    if !req.confirm {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Set \"confirm\": true to delete recordings".to_string(),
+        )
+            .into_response();
+    }
+    if !req.has_filter() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "At least one filter is required".to_string(),
+        )
+            .into_response();
+    }
+
+    let mut deleted = Vec::new();
+    let mut reclaimed: u64 = 0;
+    for entry in state.finished_entries().await.iter() {
+        if !req.matches(&state, entry).await || state.manager.is_running(&entry.name).await {
+            continue;
+        }
+        reclaimed += retention::remove_finished(&state, entry).await;
+        deleted.push(entry.name.clone());
+    }
+    if !deleted.is_empty() {
+        state.invalidate_finished().await;
+        info!(
+            count = deleted.len(),
+            reclaimed, "finished recordings deleted"
+        );
+    }
+    Json(serde_json::json!({"deleted": deleted, "reclaimed_bytes": reclaimed})).into_response()
+}
@@ -0,0 +1,70 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use tokio::fs;
+
+use super::common::vod_origin;
+use crate::{
+    archive,
+    playlist::{Playlist, dvr_window_playlist},
+    recording::sanitize_name,
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+pub struct DvrQuery {
+    /// How far back clients can seek, in seconds
+    #[serde(default = "default_window")]
+    pub window: f64,
+}
+
+fn default_window() -> f64 {
+    3600.0
+}
+
+/// `GET /api/dvr/{name}.m3u8?window=3600` - sliding playlist over the last
+/// `window` seconds of a recording in progress, for time-shifted playback.
+pub async fn dvr(
+    State(state): State<AppState>,
+    Path(file): Path<String>,
+    Query(q): Query<DvrQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let Some(raw_name) = file.strip_suffix(".m3u8") else {
+        return (StatusCode::NOT_FOUND, "expected {name}.m3u8").into_response();
+    };
+    let name = match sanitize_name(raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if q.window.is_nan() || q.window <= 0.0 {
+        return (StatusCode::BAD_REQUEST, "invalid window").into_response();
+    }
+
+    // with a live window only the archive has the older segments
+    let mut source = archive::playlist_path(&state.pending_dir, &name);
+    if fs::metadata(&source).await.is_err() {
+        source = state.pending_dir.join(format!("{}.m3u8", name));
+    }
+    let Ok(content) = fs::read_to_string(&source).await else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Recording '{}' has no pending playlist", name),
+        )
+            .into_response();
+    };
+
+    let base_url = format!("{}{}", vod_origin(&state, &headers), state.url("/live"));
+    let body = dvr_window_playlist(&Playlist::parse(&content), q.window, &base_url);
+    (
+        [
+            (header::CONTENT_TYPE, "application/vnd.apple.mpegurl"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        body.to_string(),
+    )
+        .into_response()
+}
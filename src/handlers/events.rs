@@ -0,0 +1,45 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{Stream, stream};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// Only events of this recording
+    pub name: Option<String>,
+}
+
+/// `GET /api/events/segments` - server-sent `segment` events for every
+/// segment completed from now on, JSON encoded.
+pub async fn segment_events(
+    State(state): State<AppState>,
+    Query(q): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let rx = state.segment_events.subscribe();
+    let events = stream::unfold((rx, q.name), |(mut rx, name)| async move {
+        loop {
+            match rx.recv().await {
+                Ok(ev) if name.as_ref().is_none_or(|n| *n == ev.name) => {
+                    let Ok(event) = Event::default().event("segment").json_data(&ev) else {
+                        continue;
+                    };
+                    return Some((Ok(event), (rx, name)));
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(missed, "segment event subscriber is too slow");
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
@@ -0,0 +1,32 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use tracing::error;
+
+use crate::{
+    export::{ExportReq, start_export},
+    state::AppState,
+};
+
+/// Starts an MP4 export job; poll `/api/jobs` for progress.
+pub async fn export_mp4(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    req: Option<Json<ExportReq>>,
+) -> impl IntoResponse {
+    let req = req.map(|Json(r)| r).unwrap_or_default();
+    match start_export(&state, &name, req).await {
+        Ok(id) => (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({"status":"exporting","job":id})),
+        )
+            .into_response(),
+        Err(e) => {
+            error!(error=?e, %name, "export failed to start");
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+    }
+}
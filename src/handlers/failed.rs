@@ -0,0 +1,88 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use tracing::error;
+
+use super::common::refuse_in_maintenance;
+use crate::{
+    recording::{discard_pending, sanitize_name, start_ffmpeg},
+    state::{AppState, FailedRecording},
+};
+
+/// `GET /api/failed` - recordings that ended in failure, most recent first.
+pub async fn list_failed(State(state): State<AppState>) -> Json<Vec<FailedRecording>> {
+    Json(state.manager.failed_list().await)
+}
+
+/// `POST /api/failed/{name}/retry` - start a failed recording again with its
+/// original request, continuing its pending files.
+pub async fn retry_failed(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if let Some(refused) = refuse_in_maintenance(&state) {
+        return refused;
+    }
+    let Some(failed) = state.manager.failed_entry(&name).await else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("No failed recording '{}'", name),
+        )
+            .into_response();
+    };
+    match start_ffmpeg(&state, &failed.req, true).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(serde_json::json!({"status":"started"})),
+        )
+            .into_response(),
+        Err(e) => {
+            error!(error=?e, %name, "retry failed");
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+    }
+}
+
+/// `DELETE /api/failed/{name}` - forget a failed recording and delete its
+/// partial pending files.
+pub async fn delete_failed(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if state.manager.is_running(&name).await {
+        return (
+            StatusCode::CONFLICT,
+            format!("Recording '{}' is running again", name),
+        )
+            .into_response();
+    }
+    if !state.manager.clear_failed(&name).await {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("No failed recording '{}'", name),
+        )
+            .into_response();
+    }
+    match discard_pending(&state, &name).await {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(serde_json::json!({"status":"deleted"})),
+        )
+            .into_response(),
+        Err(e) => {
+            error!(error=?e, %name, "removing pending files failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
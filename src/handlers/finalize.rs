@@ -1,26 +1,28 @@
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
 };
 use tracing::{error, info};
 
 use crate::{
-    recording::{finalize_to_vod, sanitize_name},
+    jobs::Cancelled,
+    recording::{AlreadyFinalized, FinalizeOpts, finalize_to_vod, preview_finalize, sanitize_name},
     state::AppState,
 };
 
 pub async fn finalize(
     State(state): State<AppState>,
     Path(raw_name): Path<String>,
+    Query(trim): Query<FinalizeOpts>,
 ) -> impl IntoResponse {
     let name = match sanitize_name(&raw_name) {
         Ok(n) => n,
         Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     };
     info!(%name, "finalize request received");
-    match finalize_to_vod(&state, &name).await {
+    match finalize_to_vod(&state, &name, trim).await {
         Ok(()) => {
             info!(%name, "finalization succeeded");
             (
@@ -29,9 +31,54 @@ pub async fn finalize(
             )
                 .into_response()
         }
+        Err(e) if e.is::<Cancelled>() => {
+            info!(%name, "finalize cancelled");
+            (StatusCode::CONFLICT, "finalize cancelled").into_response()
+        }
         Err(e) => {
+            // retried finalize: the client can treat this like success
+            if let Some(done) = e.downcast_ref::<AlreadyFinalized>() {
+                info!(%name, "recording already finalized");
+                return (
+                    StatusCode::CONFLICT,
+                    Json(serde_json::json!({
+                        "error": "already_finalized",
+                        "playlist": done.playlist,
+                    })),
+                )
+                    .into_response();
+            }
             error!(error=?e, %name, "finalize failed");
             (StatusCode::BAD_REQUEST, e.to_string()).into_response()
         }
     }
 }
+
+/// `GET /api/finalize/{name}/preview` - the VOD playlist and segment moves
+/// a finalize with the same options would produce, without changing files.
+pub async fn finalize_preview(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+    Query(opts): Query<FinalizeOpts>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    match preview_finalize(&state, &name, opts).await {
+        Ok(preview) => Json(preview).into_response(),
+        Err(e) => {
+            if let Some(done) = e.downcast_ref::<AlreadyFinalized>() {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(serde_json::json!({
+                        "error": "already_finalized",
+                        "playlist": done.playlist,
+                    })),
+                )
+                    .into_response();
+            }
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+    }
+}
@@ -0,0 +1,9 @@
+use axum::{Json, extract::State};
+
+use crate::state::{AppState, RecordingHealth};
+
+/// `GET /api/health/recordings` - state of all running and failed
+/// recordings in one call, for dashboards.
+pub async fn recordings_health(State(state): State<AppState>) -> Json<Vec<RecordingHealth>> {
+    Json(state.manager.health().await)
+}
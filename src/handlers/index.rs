@@ -0,0 +1,52 @@
+use axum::{
+    Json,
+    extract::State,
+    http::{StatusCode, Uri},
+    response::{Html, IntoResponse, Response},
+};
+
+use crate::state::AppState;
+
+/// `GET /` - a small overview page for people exploring the server in a
+/// browser. Disabled with `index_page: false`.
+pub async fn index(State(state): State<AppState>) -> Html<String> {
+    let running = state.manager.running_count().await;
+    let finished = state.finished_entries().await.len();
+    let links: String = [
+        ("/api/live", "Live recordings"),
+        ("/api/finished", "Finished recordings"),
+        ("/api/channels", "Channels"),
+        ("/api/health/recordings", "Recording health"),
+        ("/api/stats", "Server statistics"),
+    ]
+    .iter()
+    .map(|(path, label)| format!("<li><a href=\"{}\">{}</a></li>\n", state.url(path), label))
+    .collect();
+    Html(format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>HTTP Live DVR</title></head>\n\
+         <body>\n<h1>HTTP Live DVR</h1>\n<p>{} recording(s) running, {} finished.</p>\n\
+         <ul>\n{}</ul>\n<p>The API is described in <code>openapi.yaml</code>.</p>\n</body>\n</html>\n",
+        running, finished, links
+    ))
+}
+
+/// Unknown paths: JSON below `/api/` (also a tenant's) so API clients can
+/// parse it, plain text elsewhere.
+pub async fn not_found(State(state): State<AppState>, uri: Uri) -> Response {
+    let path = uri
+        .path()
+        .strip_prefix(state.config.base_path.as_str())
+        .unwrap_or(uri.path());
+    if path.ends_with("/api") || path.contains("/api/") {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "error": "not_found",
+                "path": uri.path(),
+                "message": "No such API endpoint; see openapi.yaml for the available ones",
+            })),
+        )
+            .into_response();
+    }
+    (StatusCode::NOT_FOUND, "Not found").into_response()
+}
@@ -0,0 +1,99 @@
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use http_body_util::BodyExt;
+use tokio::{
+    io::AsyncWriteExt,
+    process::ChildStdin,
+    time::{Duration, sleep},
+};
+use tracing::{info, warn};
+
+use crate::{recording::sanitize_name, state::AppState};
+
+/// How often to look for the stdin of a restarted ffmpeg
+const RESTART_POLL: Duration = Duration::from_millis(250);
+
+/// `POST /api/ingest/{name}` - stream the request body into the ffmpeg of
+/// a recording started with `input_url: "-"`. The recording ends when the
+/// body ends or the connection drops; if ffmpeg is restarted in between,
+/// the stream continues into the new one.
+pub async fn ingest(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+    mut body: Body,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let Some(mut stdin) = state.manager.take_stdin(&name).await else {
+        if !state.manager.is_running(&name).await {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("Recording '{}' is not running", name),
+            )
+                .into_response();
+        }
+        // not an ingest recording, already fed, or ffmpeg is restarting
+        return (
+            StatusCode::CONFLICT,
+            format!("Recording '{}' is not waiting for input", name),
+        )
+            .into_response();
+    };
+
+    info!(%name, "ingest started");
+    let mut bytes: u64 = 0;
+    while let Some(frame) = body.frame().await {
+        let frame = match frame {
+            Ok(f) => f,
+            Err(e) => {
+                warn!(%name, error=?e, bytes, "ingest connection lost - ending recording");
+                return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+            }
+        };
+        let Ok(data) = frame.into_data() else {
+            continue;
+        };
+        while let Err(e) = stdin.write_all(&data).await {
+            warn!(%name, error=?e, bytes, "ffmpeg stopped reading the ingest stream");
+            let Some(restarted) = restarted_stdin(&state, &name).await else {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("ffmpeg stopped reading input: {}", e),
+                )
+                    .into_response();
+            };
+            info!(%name, bytes, "ingest continues into the restarted ffmpeg");
+            stdin = restarted;
+        }
+        bytes += data.len() as u64;
+    }
+    // closing stdin lets ffmpeg finish the last segment and exit
+    drop(stdin);
+    info!(%name, bytes, "ingest finished");
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({"status":"ingested", "bytes": bytes})),
+    )
+        .into_response()
+}
+
+/// The stdin of the next ffmpeg run of `name`, or `None` once the recording
+/// has ended instead of restarting.
+async fn restarted_stdin(state: &AppState, name: &str) -> Option<ChildStdin> {
+    loop {
+        if let Some(stdin) = state.manager.take_stdin(name).await {
+            return Some(stdin);
+        }
+        if !state.manager.is_running(name).await {
+            return None;
+        }
+        sleep(RESTART_POLL).await;
+    }
+}
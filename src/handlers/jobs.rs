@@ -0,0 +1,28 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+
+use crate::{jobs::JobInfo, state::AppState};
+
+pub async fn list_jobs(State(state): State<AppState>) -> Json<Vec<JobInfo>> {
+    Json(state.jobs.list().await)
+}
+
+pub async fn cancel_job(State(state): State<AppState>, Path(id): Path<u64>) -> impl IntoResponse {
+    if state.jobs.cancel(id).await {
+        (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({"status":"cancelling"})),
+        )
+            .into_response()
+    } else {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Job {} not found or already ended", id),
+        )
+            .into_response()
+    }
+}
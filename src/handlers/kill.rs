@@ -0,0 +1,37 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use tracing::{error, warn};
+
+use crate::{procstat::force_kill, recording::sanitize_name, state::AppState};
+
+/// Last resort for a wedged ffmpeg that ignores `stop`: SIGKILL the process
+/// and drop the recording right away. The segment being written at that
+/// moment may be left truncated.
+pub async fn kill(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let pid = match state.manager.kill(&name).await {
+        Ok(pid) => pid,
+        Err(e) => return (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+    };
+    warn!(%name, ?pid, "force-killing recording");
+    if let Some(pid) = pid
+        && let Err(e) = force_kill(pid).await
+    {
+        error!(error=?e, %name, pid, "kill failed");
+    }
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({"status":"killed","pid":pid})),
+    )
+        .into_response()
+}
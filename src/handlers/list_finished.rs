@@ -1,26 +1,56 @@
-use axum::{Json, extract::State};
-use tokio::fs;
+use axum::{
+    Json,
+    extract::{Query, State},
+};
 
 use super::ListItem;
 use crate::state::AppState;
 
-pub async fn list_finished(State(state): State<AppState>) -> Json<Vec<ListItem>> {
-    let mut items = Vec::new();
-    if let Ok(mut rd) = fs::read_dir(&state.finished_dir).await {
-        while let Ok(Some(entry)) = rd.next_entry().await {
-            let p = entry.path();
-            if p.is_dir() {
-                let idx = p.join("index.m3u8");
-                if idx.exists() {
-                    if let Some(name) = p.file_name().and_then(|s| s.to_str()) {
-                        items.push(ListItem {
-                            name: name.to_string(),
-                            playlist: format!("/vod/{}/index.m3u8", name),
-                        });
-                    }
-                }
-            }
-        }
-    }
+/// `GET /api/finished?tag=a&tag=b` only returns recordings carrying all given tags.
+pub async fn list_finished(
+    State(state): State<AppState>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Json<Vec<ListItem>> {
+    let wanted: Vec<&str> = params
+        .iter()
+        .filter(|(k, _)| k == "tag")
+        .map(|(_, v)| v.as_str())
+        .collect();
+
+    let items = finished_items(&state)
+        .await
+        .into_iter()
+        .filter(|item| wanted.iter().all(|t| item.tags.iter().any(|x| x == t)))
+        .collect();
     Json(items)
 }
+
+/// All finalized recordings with their metadata.
+pub(super) async fn finished_items(state: &AppState) -> Vec<ListItem> {
+    state
+        .finished_entries()
+        .await
+        .iter()
+        .map(|entry| {
+            let name = &entry.name;
+            ListItem {
+                name: name.clone(),
+                playlist: state.url(&format!("/vod/{}/{}", name, entry.playlist)),
+                tags: entry.meta.tags.clone(),
+                channel: entry.meta.channel.clone(),
+                audio: entry
+                    .meta
+                    .audio
+                    .iter()
+                    .map(|f| state.url(&format!("/vod/{}/{}", name, f)))
+                    .collect(),
+                master: entry
+                    .meta
+                    .master
+                    .as_ref()
+                    .map(|f| state.url(&format!("/vod/{}/{}", name, f))),
+                duration_secs: entry.meta.duration_secs,
+            }
+        })
+        .collect()
+}
@@ -2,24 +2,39 @@ use axum::{Json, extract::State};
 use tokio::fs;
 
 use super::ListItem;
-use crate::state::AppState;
+use crate::{meta, recording::is_audio_file, state::AppState};
 
 pub async fn list_live(State(state): State<AppState>) -> Json<Vec<ListItem>> {
-    let mut items = Vec::new();
+    let mut files = Vec::new();
     if let Ok(mut rd) = fs::read_dir(&state.pending_dir).await {
         while let Ok(Some(entry)) = rd.next_entry().await {
-            let p = entry.path();
-            if p.extension().and_then(|s| s.to_str()) == Some("m3u8") {
-                if let Some(stem) = p.file_stem().and_then(|s| s.to_str()) {
-                    if let Some(fname) = p.file_name() {
-                        items.push(ListItem {
-                            name: stem.to_string(),
-                            playlist: format!("/live/{}", fname.to_string_lossy()),
-                        });
-                    }
-                }
+            if let Ok(fname) = entry.file_name().into_string() {
+                files.push(fname);
             }
         }
     }
+    files.sort();
+
+    let mut items = Vec::new();
+    for fname in &files {
+        // recording names have no dots; this skips subtitle lists
+        let Some(stem) = fname.strip_suffix(".m3u8").filter(|s| !s.contains('.')) else {
+            continue;
+        };
+        let rec_meta = meta::load(&meta::pending_path(&state.pending_dir, stem)).await;
+        items.push(ListItem {
+            name: stem.to_string(),
+            playlist: state.url(&format!("/live/{}", fname)),
+            tags: rec_meta.tags,
+            channel: rec_meta.channel,
+            audio: files
+                .iter()
+                .filter(|f| is_audio_file(stem, f))
+                .map(|f| state.url(&format!("/live/{}", f)))
+                .collect(),
+            master: None,
+            duration_secs: None,
+        });
+    }
     Json(items)
 }
@@ -0,0 +1,44 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use tokio::fs;
+use tracing::{error, info};
+
+use crate::{reclog, recording::sanitize_name, state::AppState};
+
+/// `POST /api/logs/{name}/rotate` - rotate a recording's ffmpeg log now
+/// instead of waiting for `log_max_bytes`.
+pub async fn rotate_log(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let path = reclog::path(&state.logs_dir, &name);
+    if fs::metadata(&path).await.is_err() {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("No log for recording '{}'", name),
+        )
+            .into_response();
+    }
+    match reclog::rotate(&path, state.settings.load().log_keep).await {
+        Ok(()) => {
+            info!(%name, "recording log rotated");
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({"status":"rotated"})),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!(error=?e, %name, "log rotation failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::atomic::Ordering;
+
+use axum::{Json, extract::State};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::state::AppState;
+
+#[derive(Deserialize)]
+pub struct MaintenanceReq {
+    pub enabled: bool,
+}
+
+/// `POST /api/maintenance` - while enabled, starts (direct, retried and
+/// queued ones) are refused with 503; running recordings, stop, finalize
+/// and listings are not affected. Not persisted across restarts.
+pub async fn set_maintenance(
+    State(state): State<AppState>,
+    Json(req): Json<MaintenanceReq>,
+) -> Json<serde_json::Value> {
+    let was = state.maintenance.swap(req.enabled, Ordering::Relaxed);
+    if was != req.enabled {
+        info!(enabled = req.enabled, "maintenance mode changed");
+    }
+    Json(serde_json::json!({"maintenance": req.enabled}))
+}
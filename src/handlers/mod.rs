@@ -1,13 +1,63 @@
+pub mod channels;
+pub mod clip;
 mod common;
+pub mod config;
+pub mod delete_finished;
+pub mod dvr;
+pub mod events;
+pub mod export;
+pub mod failed;
 pub mod finalize;
+pub mod health;
+pub mod index;
+pub mod ingest;
+pub mod jobs;
+pub mod kill;
 pub mod list_finished;
 pub mod list_live;
+pub mod logs;
+pub mod maintenance;
+pub mod playlist;
+pub mod probe;
+pub mod queue;
+pub mod reconfigure;
+pub mod segments;
+pub mod selftest;
+pub mod snapshot;
 pub mod start;
+pub mod stats;
+pub mod status;
 pub mod stop;
+pub mod tags;
 
+pub use channels::{channel_recordings, list_channels};
+pub use clip::clip;
 pub use common::ListItem;
-pub use finalize::finalize;
+pub use config::get_config;
+pub use delete_finished::delete_finished;
+pub use dvr::dvr;
+pub use events::segment_events;
+pub use export::export_mp4;
+pub use failed::{delete_failed, list_failed, retry_failed};
+pub use finalize::{finalize, finalize_preview};
+pub use health::recordings_health;
+pub use index::{index, not_found};
+pub use ingest::ingest;
+pub use jobs::{cancel_job, list_jobs};
+pub use kill::kill;
 pub use list_finished::list_finished;
 pub use list_live::list_live;
+pub use logs::rotate_log;
+pub use maintenance::set_maintenance;
+pub use playlist::wait_playlist;
+pub use probe::probe_source;
+pub use queue::{cancel_queued, list_queue};
+pub use reconfigure::reconfigure;
+pub use segments::list_segments;
+pub use selftest::run_selftest;
+pub use snapshot::snapshot;
 pub use start::start;
+pub use stats::stats;
+pub use status::status;
 pub use stop::stop;
+pub use tags::update_tags;
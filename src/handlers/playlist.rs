@@ -0,0 +1,77 @@
+use std::time::UNIX_EPOCH;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use tokio::{
+    fs,
+    time::{Duration, Instant, sleep},
+};
+
+use crate::{recording::sanitize_name, state::AppState};
+
+/// Playlist changes are noticed within this interval.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const DEFAULT_WAIT_SECS: u64 = 30;
+const MAX_WAIT_SECS: u64 = 120;
+
+#[derive(Deserialize)]
+pub struct WaitQuery {
+    /// `mtime` of the playlist the client already has (0 = none)
+    #[serde(default)]
+    pub since: u64,
+    /// Seconds to wait for a change
+    pub timeout: Option<u64>,
+}
+
+/// `GET /api/playlist/{name}/wait` - the live playlist of a recording once
+/// it differs from `since`, with its mtime in milliseconds. Answers
+/// immediately if it already does, otherwise waits up to `timeout` seconds
+/// and returns 204 if nothing changed.
+pub async fn wait_playlist(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+    Query(q): Query<WaitQuery>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let playlist = state.pending_dir.join(format!("{}.m3u8", name));
+    state.manager.touch(&name).await;
+    let wait = Duration::from_secs(q.timeout.unwrap_or(DEFAULT_WAIT_SECS).min(MAX_WAIT_SECS));
+    let deadline = Instant::now() + wait;
+
+    loop {
+        let Some(mtime) = mtime_ms(&playlist).await else {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("Recording '{}' has no live playlist", name),
+            )
+                .into_response();
+        };
+        if mtime != q.since {
+            // read after the stat: a rewrite in between is reported again
+            // on the next call rather than missed
+            return match fs::read_to_string(&playlist).await {
+                Ok(content) => {
+                    Json(serde_json::json!({"mtime": mtime, "playlist": content})).into_response()
+                }
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+            };
+        }
+        if Instant::now() >= deadline {
+            return StatusCode::NO_CONTENT.into_response();
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn mtime_ms(path: &std::path::Path) -> Option<u64> {
+    let modified = fs::metadata(path).await.and_then(|m| m.modified()).ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64)
+}
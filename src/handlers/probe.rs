@@ -0,0 +1,27 @@
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde::Deserialize;
+use tracing::error;
+
+use crate::{probe::probe_input, state::AppState};
+
+#[derive(Deserialize)]
+pub struct ProbeReq {
+    pub input_url: String,
+    /// Ignore a cached result and run ffprobe again
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// Streams and format of an input, as reported by ffprobe.
+pub async fn probe_source(
+    State(state): State<AppState>,
+    Json(req): Json<ProbeReq>,
+) -> impl IntoResponse {
+    match probe_input(&state, &req.input_url, req.refresh).await {
+        Ok(info) => Json(info).into_response(),
+        Err(e) => {
+            error!(error=?e, "probe failed");
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+
+use crate::{queue::QueueItem, recording::sanitize_name, state::AppState};
+
+/// `GET /api/queue` - starts waiting for a free slot, next first.
+pub async fn list_queue(State(state): State<AppState>) -> Json<Vec<QueueItem>> {
+    Json(state.queue.list().await)
+}
+
+/// `DELETE /api/queue/{name}` - cancel a queued start.
+pub async fn cancel_queued(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if !state.queue.remove(&name).await {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Recording '{}' is not queued", name),
+        )
+            .into_response();
+    }
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({"status":"dequeued"})),
+    )
+        .into_response()
+}
@@ -0,0 +1,130 @@
+use anyhow::Result;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde_json::{Map, Value};
+use tracing::{error, info};
+
+use crate::{
+    recording::{StartReq, sanitize_name, start_ffmpeg},
+    state::AppState,
+};
+
+/// Fields tied to files the running recording already has on disk.
+const FIXED_FIELDS: &[&str] = &[
+    "name",
+    "live_window_segments",
+    "preserve_all_audio",
+    "segment_subdir",
+    "iframe_playlist",
+    "extract_subtitles",
+    "fifo_output",
+];
+
+/// `POST /api/reconfigure/{name}` - restart a running recording with some
+/// of its start fields changed (e.g. `hls_time` or `input_url`). ffmpeg
+/// appends to the same playlist after a discontinuity. If the new settings
+/// cannot be started, the recording continues with the previous ones.
+pub async fn reconfigure(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+    Json(changes): Json<Map<String, Value>>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let Some(current) = state.manager.running_req(&name).await else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Recording '{}' is not running", name),
+        )
+            .into_response();
+    };
+    let req = match apply_changes(&current, changes) {
+        Ok(r) => r,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    match state.manager.stop_and_wait(&name).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                StatusCode::CONFLICT,
+                format!("Recording '{}' stopped in the meantime", name),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            error!(error=?e, %name, "stop for reconfigure failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+        }
+    }
+
+    match start_ffmpeg(&state, &req, true).await {
+        Ok(_) => {
+            info!(%name, "recording reconfigured");
+            (
+                StatusCode::OK,
+                Json(serde_json::json!({"status":"reconfigured"})),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!(error=?e, %name, "reconfigure failed - restarting with previous settings");
+            if let Err(restart) = start_ffmpeg(&state, &current, true).await {
+                error!(error=?restart, %name, "restart with previous settings failed");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!(
+                        "Recording '{}' is stopped: the new settings failed ({}) and restarting with the previous ones failed too ({})",
+                        name, e, restart
+                    ),
+                )
+                    .into_response();
+            }
+            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+        }
+    }
+}
+
+/// Overlay the given fields on the running request.
+fn apply_changes(current: &StartReq, changes: Map<String, Value>) -> Result<StartReq> {
+    let mut value = serde_json::to_value(current)?;
+    let Some(fields) = value.as_object_mut() else {
+        anyhow::bail!("start request is not an object");
+    };
+    for (key, new) in changes {
+        if FIXED_FIELDS.contains(&key.as_str()) && fields.get(&key) != Some(&new) {
+            anyhow::bail!("{} cannot be changed while recording", key);
+        }
+        fields.insert(key, new);
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_tied_to_files_cannot_change() {
+        let current: StartReq =
+            serde_json::from_value(serde_json::json!({"name": "cam1", "input_url": "in.ts"}))
+                .unwrap();
+        for (key, value) in [
+            ("segment_subdir", Value::Bool(true)),
+            ("iframe_playlist", Value::Bool(true)),
+            ("extract_subtitles", Value::Bool(true)),
+            ("fifo_output", Value::from("cam1.ts")),
+        ] {
+            let changes = Map::from_iter([(key.to_string(), value)]);
+            assert!(apply_changes(&current, changes).is_err(), "{key}");
+        }
+        let changes = Map::from_iter([("hls_time".to_string(), Value::from(4))]);
+        assert_eq!(apply_changes(&current, changes).unwrap().hls_time, 4);
+    }
+}
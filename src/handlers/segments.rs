@@ -0,0 +1,70 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{
+    playlist::{parse_segments, uri_path},
+    recording::sanitize_name,
+    state::AppState,
+};
+
+#[derive(Serialize)]
+pub struct SegmentItem {
+    pub uri: String,
+    pub duration: f64,
+    pub program_date_time: Option<String>,
+    /// Path on the VOD server
+    pub url: String,
+}
+
+/// Segments of a live or finished recording in playlist order.
+pub async fn list_segments(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let pending_pl = state.pending_dir.join(format!("{}.m3u8", name));
+    let (content, base_url) = if let Ok(c) = fs::read_to_string(&pending_pl).await {
+        (c, state.url("/live"))
+    } else if let Some(file) = state.find_vod_playlist(&name).await {
+        match fs::read_to_string(state.finished_dir.join(&name).join(file)).await {
+            Ok(c) => (c, state.url(&format!("/vod/{}", name))),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    } else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Recording '{}' not found", name),
+        )
+            .into_response();
+    };
+
+    let items: Vec<SegmentItem> = parse_segments(&content)
+        .into_iter()
+        .map(|s| {
+            let path = uri_path(&s.uri);
+            // relative paths stay, live segments may be in `{name}/`
+            let file = if path.starts_with('/') || path.contains("://") {
+                path.rsplit('/').next().unwrap_or(path)
+            } else {
+                path
+            };
+            SegmentItem {
+                url: format!("{}/{}", base_url, file),
+                uri: s.uri,
+                duration: s.duration,
+                program_date_time: s.program_date_time,
+            }
+        })
+        .collect();
+    Json(items).into_response()
+}
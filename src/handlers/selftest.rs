@@ -0,0 +1,14 @@
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+
+use crate::{selftest, state::AppState};
+
+/// Record, finalize and verify a short synthetic recording.
+pub async fn run_selftest(State(state): State<AppState>) -> impl IntoResponse {
+    let report = selftest::run(&state).await;
+    let status = if report.ok {
+        StatusCode::OK
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    (status, Json(report))
+}
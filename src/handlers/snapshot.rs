@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use tokio::fs;
+use tracing::error;
+
+use crate::{
+    ffmpeg::extract_last_frame,
+    recording::{extract_segment_list, normalize_segment_path, sanitize_name},
+    state::AppState,
+};
+
+/// Snapshots are reused for this long to keep polling clients from
+/// spawning an ffmpeg per request.
+const SNAPSHOT_TTL: Duration = Duration::from_secs(2);
+
+/// `GET /api/snapshot/{name}.jpg` - last frame of the newest segment of a live recording.
+pub async fn snapshot(
+    State(state): State<AppState>,
+    Path(file): Path<String>,
+) -> impl IntoResponse {
+    let Some(raw_name) = file.strip_suffix(".jpg") else {
+        return (StatusCode::NOT_FOUND, "expected {name}.jpg").into_response();
+    };
+    let name = match sanitize_name(raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if !state.manager.is_running(&name).await {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Recording '{}' is not running", name),
+        )
+            .into_response();
+    }
+
+    if let Some((taken, jpeg)) = state.snapshots.lock().await.get(&name)
+        && taken.elapsed() < SNAPSHOT_TTL
+    {
+        return jpeg_response(jpeg.clone());
+    }
+
+    let playlist = state.pending_dir.join(format!("{}.m3u8", name));
+    let content = fs::read_to_string(&playlist).await.unwrap_or_default();
+    let Some(last) = extract_segment_list(&content).pop() else {
+        return (StatusCode::NOT_FOUND, "no segments recorded yet").into_response();
+    };
+    let segment = match normalize_segment_path(&state.pending_dir, &last).await {
+        Ok(p) => p,
+        Err(e) => return (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+    };
+
+    match extract_last_frame(&segment).await {
+        Ok(jpeg) => {
+            let mut cache = state.snapshots.lock().await;
+            cache.retain(|_, (taken, _)| taken.elapsed() < SNAPSHOT_TTL);
+            cache.insert(name, (Instant::now(), jpeg.clone()));
+            jpeg_response(jpeg)
+        }
+        Err(e) => {
+            error!(error=?e, %name, "snapshot failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+fn jpeg_response(jpeg: Vec<u8>) -> axum::response::Response {
+    (
+        [
+            (header::CONTENT_TYPE, "image/jpeg"),
+            (header::CACHE_CONTROL, "max-age=2"),
+        ],
+        jpeg,
+    )
+        .into_response()
+}
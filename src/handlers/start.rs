@@ -1,22 +1,208 @@
-use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use std::path::Path;
+
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value};
+use tokio::{
+    fs,
+    sync::oneshot,
+    time::{Duration, Instant, interval},
+};
 use tracing::error;
 
+use super::common::refuse_in_maintenance;
 use crate::{
-    recording::{StartReq, start_ffmpeg},
-    state::AppState,
+    config::START_CONTROL_FIELDS,
+    playlist::parse_segments,
+    recording::{StartReq, sanitize_name, start_ffmpeg},
+    state::{AppState, AtCapacity, WriterBusy},
 };
 
-pub async fn start(State(state): State<AppState>, Json(req): Json<StartReq>) -> impl IntoResponse {
+pub async fn start(State(state): State<AppState>, Json(body): Json<Value>) -> impl IntoResponse {
+    if let Some(refused) = refuse_in_maintenance(&state) {
+        return refused;
+    }
+    let req = match with_defaults(body, &state.settings.load().default_start) {
+        Ok(req) => req,
+        Err(e) => return (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response(),
+    };
+    let playlist = state.pending_dir.join(format!("{}.m3u8", req.name));
+    let existing = if req.wait_for_first_segment && sanitize_name(&req.name).is_ok() {
+        segment_count(&playlist).await
+    } else {
+        0
+    };
+    if state.queue.contains(&req.name).await {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Recording '{}' is already queued", req.name),
+        )
+            .into_response();
+    }
     // Allow resuming an existing recording when the client requests it.
-    match start_ffmpeg(&state, &req, req.resume).await {
-        Ok(()) => (
+    let first_failure = match start_ffmpeg(&state, &req, req.resume).await {
+        Ok(rx) => rx,
+        Err(e) if req.queue_if_full && e.is::<AtCapacity>() => {
+            return match state.queue.push(req).await {
+                Ok(position) => (
+                    StatusCode::ACCEPTED,
+                    Json(serde_json::json!({"status":"queued", "position": position})),
+                )
+                    .into_response(),
+                Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+            };
+        }
+        Err(e) if e.is::<WriterBusy>() => {
+            return (StatusCode::CONFLICT, e.to_string()).into_response();
+        }
+        Err(e) => {
+            error!(error=?e, "start_ffmpeg failed");
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+    };
+    if !req.wait_for_first_segment {
+        return (
             StatusCode::OK,
             Json(serde_json::json!({"status":"started"})),
         )
-            .into_response(),
-        Err(e) => {
-            error!(error=?e, "start_ffmpeg failed");
-            (StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            .into_response();
+    }
+    wait_for_first_segment(&state, &req, &playlist, existing, first_failure).await
+}
+
+/// Stays below the request timeout of the start route
+const FIRST_SEGMENT_TIMEOUT: Duration = Duration::from_secs(25);
+
+/// Wait until the playlist lists more than `existing` segments, the first
+/// ffmpeg run fails, or the timeout expires. A failed recording is stopped
+/// so the client can fix the request and try again.
+async fn wait_for_first_segment(
+    state: &AppState,
+    req: &StartReq,
+    playlist: &Path,
+    existing: usize,
+    mut first_failure: oneshot::Receiver<String>,
+) -> Response {
+    let deadline = Instant::now() + FIRST_SEGMENT_TIMEOUT;
+    let mut poll = interval(Duration::from_millis(500));
+    let mut ffmpeg_running = true;
+    loop {
+        tokio::select! {
+            res = &mut first_failure, if ffmpeg_running => {
+                ffmpeg_running = false;
+                if let Ok(stderr) = res {
+                    let _ = state.manager.stop(&req.name).await;
+                    return (
+                        StatusCode::BAD_GATEWAY,
+                        format!("ffmpeg failed before the first segment:\n{}", stderr),
+                    )
+                        .into_response();
+                }
+            }
+            _ = poll.tick() => {
+                let content = fs::read_to_string(playlist).await.unwrap_or_default();
+                let segments = parse_segments(&content);
+                if segments.len() > existing {
+                    return (
+                        StatusCode::OK,
+                        Json(serde_json::json!({
+                            "status": "started",
+                            "first_segment": segments[existing].uri,
+                        })),
+                    )
+                        .into_response();
+                }
+                if !state.manager.is_running(&req.name).await {
+                    return (
+                        StatusCode::BAD_GATEWAY,
+                        "recording ended before the first segment was written",
+                    )
+                        .into_response();
+                }
+                if Instant::now() >= deadline {
+                    return (
+                        StatusCode::GATEWAY_TIMEOUT,
+                        "no segment written yet; the recording keeps running",
+                    )
+                        .into_response();
+                }
+            }
+        }
+    }
+}
+
+async fn segment_count(playlist: &Path) -> usize {
+    let content = fs::read_to_string(playlist).await.unwrap_or_default();
+    parse_segments(&content).len()
+}
+
+/// Fill fields missing from the request body from the configured
+/// `default_start`; values sent by the client always win. Control fields
+/// such as `resume` only ever come from the request.
+fn with_defaults(mut body: Value, defaults: &Map<String, Value>) -> serde_json::Result<StartReq> {
+    if let Some(obj) = body.as_object_mut() {
+        for (key, value) in defaults {
+            if START_CONTROL_FIELDS.contains(&key.as_str()) {
+                continue;
+            }
+            obj.entry(key).or_insert_with(|| value.clone());
         }
     }
+    serde_json::from_value(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, testutil};
+
+    #[test]
+    fn defaults_fill_any_missing_field() {
+        let defaults = serde_json::json!({
+            "hls_time": 4,
+            "auto_finalize": true,
+            "tags": ["default"],
+        });
+        let body = serde_json::json!({
+            "name": "cam1",
+            "input_url": "http://example.com/live.m3u8",
+            "tags": ["news"],
+        });
+        let req = with_defaults(body, defaults.as_object().unwrap()).unwrap();
+        assert_eq!(req.hls_time, 4);
+        assert!(req.auto_finalize);
+        assert_eq!(req.tags, ["news"]);
+    }
+
+    #[test]
+    fn defaults_never_set_control_fields() {
+        let defaults = serde_json::json!({"resume": true, "priority": 5});
+        let body = serde_json::json!({"name": "cam1", "input_url": "in.ts"});
+        let req = with_defaults(body, defaults.as_object().unwrap()).unwrap();
+        assert!(!req.resume);
+        assert_eq!(req.priority, 0);
+    }
+
+    #[tokio::test]
+    async fn resume_while_the_stopped_run_still_writes_is_a_conflict() {
+        let dir = testutil::TempDir::new();
+        let state = testutil::app_state(dir.path(), Config::default()).await;
+        // a stopped run whose ffmpeg has not exited yet
+        let _writer = state.manager.claim_writer("cam1").unwrap();
+        let body = serde_json::json!({
+            "name": "cam1",
+            "input_url": "rtmp://example.com/live",
+            "resume": true,
+        });
+
+        let req: StartReq = serde_json::from_value(body.clone()).unwrap();
+        let err = start_ffmpeg(&state, &req, true).await.err().unwrap();
+        assert!(err.is::<WriterBusy>());
+        let resp = start(State(state), Json(body)).await.into_response();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
 }
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+
+use axum::{Json, extract::State};
+use serde::Serialize;
+use tokio::fs;
+
+use crate::state::AppState;
+
+#[derive(Serialize)]
+pub struct StatsResponse {
+    pub active_recordings: usize,
+    pub finished_recordings: usize,
+    pub failed_recordings: usize,
+    /// Size of everything below the pending and finished directories
+    pub bytes_used: u64,
+    pub uptime_secs: u64,
+    /// ffmpeg restarts after errors since the server started
+    pub ffmpeg_restarts: u64,
+    /// Latest timed test write to the pending directory
+    pub disk_write_latency_ms: Option<u64>,
+    /// Test writes slower than `disk_latency_warn_ms`
+    pub slow_disk_writes: u64,
+    /// New starts are refused, see `/api/maintenance`
+    pub maintenance: bool,
+}
+
+/// Quick overview of the server for humans and simple monitoring.
+pub async fn stats(State(state): State<AppState>) -> Json<StatsResponse> {
+    let finished = state.finished_entries().await.len();
+
+    Json(StatsResponse {
+        active_recordings: state.manager.running_count().await,
+        finished_recordings: finished,
+        failed_recordings: state.manager.failed_count().await,
+        bytes_used: dir_size(state.pending_dir.clone()).await
+            + dir_size(state.finished_dir.clone()).await,
+        uptime_secs: state.started.elapsed().as_secs(),
+        ffmpeg_restarts: state.manager.restarts(),
+        disk_write_latency_ms: state.disk.last_ms(),
+        slow_disk_writes: state.disk.slow_writes(),
+        maintenance: state.in_maintenance(),
+    })
+}
+
+/// Total size of all files below `dir`; symlinks are not followed.
+async fn dir_size(dir: PathBuf) -> u64 {
+    let mut total = 0;
+    let mut stack = vec![dir];
+    while let Some(dir) = stack.pop() {
+        let Ok(mut rd) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = rd.next_entry().await {
+            let Ok(ft) = entry.file_type().await else {
+                continue;
+            };
+            if ft.is_dir() {
+                stack.push(entry.path());
+            } else if ft.is_file()
+                && let Ok(md) = entry.metadata().await
+            {
+                total += md.len();
+            }
+        }
+    }
+    total
+}
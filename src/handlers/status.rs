@@ -0,0 +1,49 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Serialize;
+
+use crate::{
+    procstat::{ProcessUsage, process_usage},
+    recording::sanitize_name,
+    state::AppState,
+};
+
+#[derive(Serialize)]
+pub struct StatusResponse {
+    pub name: String,
+    /// PID of the current ffmpeg process, `null` while restarting
+    pub pid: Option<u32>,
+    #[serde(flatten)]
+    pub usage: ProcessUsage,
+}
+
+pub async fn status(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let Some(pid) = state.manager.pid(&name).await else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Recording '{}' is not running", name),
+        )
+            .into_response();
+    };
+    let usage = match pid {
+        Some(pid) => process_usage(pid).await,
+        None => None,
+    };
+    Json(StatusResponse {
+        name,
+        pid: pid.filter(|_| usage.is_some()),
+        usage: usage.unwrap_or_default(),
+    })
+    .into_response()
+}
@@ -5,6 +5,9 @@ use axum::{
     response::IntoResponse,
 };
 
+use tokio::fs;
+use tracing::error;
+
 use crate::{recording::sanitize_name, state::AppState};
 
 pub async fn stop(
@@ -16,11 +19,61 @@ pub async fn stop(
         Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     };
     match state.manager.stop(&name).await {
-        Ok(()) => (
+        Ok(true) => (
             StatusCode::OK,
             Json(serde_json::json!({"status":"stopped"})),
         )
             .into_response(),
-        Err(e) => (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+        Ok(false) => {
+            // tell "already stopped" apart from "never existed"
+            let pending = state.pending_dir.join(format!("{}.m3u8", name));
+            if fs::metadata(&pending).await.is_ok()
+                || state.find_vod_playlist(&name).await.is_some()
+            {
+                (
+                    StatusCode::CONFLICT,
+                    format!("Recording '{}' is not running", name),
+                )
+                    .into_response()
+            } else {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("Recording '{}' not found", name),
+                )
+                    .into_response()
+            }
+        }
+        Err(e) => {
+            error!(error=?e, %name, "stop failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, testutil};
+
+    #[tokio::test]
+    async fn stop_tells_unknown_and_stopped_recordings_apart() {
+        let dir = testutil::TempDir::new();
+        let state = testutil::app_state(dir.path(), Config::default()).await;
+
+        let resp = stop(State(state.clone()), Path("cam1".into()))
+            .await
+            .into_response();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // stopping again after the recording ended is a conflict, not an error
+        fs::write(state.pending_dir.join("cam1.m3u8"), "#EXTM3U\n")
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            let resp = stop(State(state.clone()), Path("cam1".into()))
+                .await
+                .into_response();
+            assert_eq!(resp.status(), StatusCode::CONFLICT);
+        }
     }
 }
@@ -0,0 +1,50 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+
+use crate::{
+    meta::{self, sanitize_tags},
+    recording::sanitize_name,
+    state::AppState,
+};
+
+#[derive(Deserialize)]
+pub struct TagsReq {
+    pub tags: Vec<String>,
+}
+
+pub async fn update_tags(
+    State(state): State<AppState>,
+    Path(raw_name): Path<String>,
+    Json(req): Json<TagsReq>,
+) -> impl IntoResponse {
+    let name = match sanitize_name(&raw_name) {
+        Ok(n) => n,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let tags = match sanitize_tags(&req.tags) {
+        Ok(t) => t,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    if state.find_vod_playlist(&name).await.is_none() {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Recording '{}' is not finalized", name),
+        )
+            .into_response();
+    }
+
+    let path = meta::finished_path(&state.finished_dir, &name);
+    let mut rec_meta = meta::load(&path).await;
+    rec_meta.tags = tags;
+    let saved = meta::save(&path, &rec_meta).await;
+    state.invalidate_finished().await;
+    match saved {
+        Ok(()) => (StatusCode::OK, Json(rec_meta)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
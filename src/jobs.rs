@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// Returned by a job that stopped because it was cancelled.
+#[derive(Debug, thiserror::Error)]
+#[error("job cancelled")]
+pub struct Cancelled;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Finalize,
+    Export,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Done,
+    Cancelled,
+    Failed { error: String },
+}
+
+impl JobStatus {
+    fn of<T>(result: &Result<T>) -> Self {
+        match result {
+            Ok(_) => JobStatus::Done,
+            Err(e) if e.is::<Cancelled>() => JobStatus::Cancelled,
+            Err(e) => JobStatus::Failed {
+                error: format!("{:#}", e),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub kind: JobKind,
+    pub name: String,
+    #[serde(flatten)]
+    pub status: JobStatus,
+    /// Fraction done (0.0 - 1.0), if the job reports progress
+    pub progress: Option<f64>,
+}
+
+/// How long a job stays listed after it has ended, so clients polling
+/// `/api/jobs` see how it went
+const ENDED_JOB_RETENTION: Duration = Duration::from_secs(300);
+
+struct Job {
+    info: JobInfo,
+    cancel: CancellationToken,
+}
+
+/// Long-running operations that can be cancelled via the API.
+#[derive(Default)]
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    jobs: Mutex<HashMap<u64, Job>>,
+}
+
+/// Marks the job as ended when the operation ends, with the status given
+/// to `finish`, and removes it from the registry after a while.
+pub struct JobGuard {
+    registry: Arc<JobRegistry>,
+    pub id: u64,
+    pub cancel: CancellationToken,
+    ended: std::sync::Mutex<Option<JobStatus>>,
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        // nothing left to update once the runtime is gone (shutdown)
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let registry = self.registry.clone();
+        let id = self.id;
+        let status = self
+            .ended
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .unwrap_or(JobStatus::Failed {
+                error: "job ended without a result".to_string(),
+            });
+        runtime.spawn(async move {
+            if let Some(job) = registry.jobs.lock().await.get_mut(&id) {
+                job.info.status = status;
+            }
+            tokio::time::sleep(ENDED_JOB_RETENTION).await;
+            registry.jobs.lock().await.remove(&id);
+        });
+    }
+}
+
+impl JobGuard {
+    /// Record how the job ended; it is listed with this status once the
+    /// guard is dropped.
+    pub fn finish<T>(&self, result: &Result<T>) {
+        *self.ended.lock().unwrap_or_else(PoisonError::into_inner) = Some(JobStatus::of(result));
+    }
+
+    pub async fn set_progress(&self, progress: f64) {
+        if let Some(job) = self.registry.jobs.lock().await.get_mut(&self.id) {
+            job.info.progress = Some(progress.clamp(0.0, 1.0));
+        }
+    }
+}
+
+impl JobRegistry {
+    pub async fn register(self: &Arc<Self>, kind: JobKind, name: &str) -> JobGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let cancel = CancellationToken::new();
+        self.jobs.lock().await.insert(
+            id,
+            Job {
+                info: JobInfo {
+                    id,
+                    kind,
+                    name: name.to_string(),
+                    status: JobStatus::Running,
+                    progress: None,
+                },
+                cancel: cancel.clone(),
+            },
+        );
+        JobGuard {
+            registry: self.clone(),
+            id,
+            cancel,
+            ended: Default::default(),
+        }
+    }
+
+    pub async fn list(&self) -> Vec<JobInfo> {
+        let jobs = self.jobs.lock().await;
+        let mut list: Vec<JobInfo> = jobs.values().map(|j| j.info.clone()).collect();
+        list.sort_by_key(|j| j.id);
+        list
+    }
+
+    /// Returns false if no such job is running.
+    pub async fn cancel(&self, id: u64) -> bool {
+        match self.jobs.lock().await.get(&id) {
+            Some(job) if matches!(job.info.status, JobStatus::Running) => {
+                job.cancel.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ended_jobs_stay_listed_with_their_status() {
+        let registry = Arc::new(JobRegistry::default());
+        let done = registry.register(JobKind::Finalize, "cam1").await;
+        let failed = registry.register(JobKind::Export, "cam2").await;
+        done.finish(&anyhow::Ok(()));
+        failed.finish::<()>(&Err(anyhow::anyhow!("disk full")));
+        let (done_id, failed_id) = (done.id, failed.id);
+        drop(done);
+        drop(failed);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let list = registry.list().await;
+        assert!(matches!(list[0].status, JobStatus::Done));
+        match &list[1].status {
+            JobStatus::Failed { error } => assert_eq!(error, "disk full"),
+            other => panic!("unexpected status {:?}", other),
+        }
+        let json = serde_json::to_value(&list[1]).unwrap();
+        assert_eq!(json["status"], "failed");
+        assert_eq!(json["error"], "disk full");
+        // only running jobs can be cancelled
+        assert!(!registry.cancel(done_id).await);
+        assert!(!registry.cancel(failed_id).await);
+    }
+
+    #[test]
+    fn guard_dropped_after_the_runtime_does_not_panic() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let registry = Arc::new(JobRegistry::default());
+        let guard = runtime.block_on(registry.register(JobKind::Finalize, "cam1"));
+        drop(runtime);
+        drop(guard);
+    }
+}
@@ -0,0 +1,126 @@
+//! Conditional requests for the live playlists.
+//!
+//! ffmpeg rewrites `{name}.m3u8` (temp file + rename) after every segment,
+//! sometimes several times within one second. `ServeDir` only compares
+//! `Last-Modified` at HTTP-date resolution, so a client that fetched the
+//! playlist before a rewrite in the same second would get a 304 for a
+//! playlist it has never seen. This middleware adds a strong `ETag` built
+//! from the full-precision metadata and only lets `Last-Modified` through
+//! once its second is over, so both validators stay accurate.
+//!
+//! `track_views` notes playlist requests for the idle auto-stop.
+
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::state::RecordingManager;
+
+pub async fn live_playlist_conditional(
+    State(pending_dir): State<Arc<PathBuf>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let file = req.uri().path().rsplit('/').next().unwrap_or_default();
+    if !file.ends_with(".m3u8") {
+        return next.run(req).await;
+    }
+    let Ok(meta) = tokio::fs::metadata(pending_dir.join(file)).await else {
+        return next.run(req).await;
+    };
+    let etag = etag(&meta);
+
+    let headers = req.headers_mut();
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        let matched = if_none_match.to_str().is_ok_and(|v| {
+            v.split(',')
+                .map(|t| t.trim().trim_start_matches("W/"))
+                .any(|t| t == "*" || t == etag)
+        });
+        if matched {
+            return (
+                StatusCode::NOT_MODIFIED,
+                [
+                    (header::ETAG, etag),
+                    (header::CACHE_CONTROL, "no-cache".into()),
+                ],
+            )
+                .into_response();
+        }
+        // If-None-Match takes precedence (RFC 9110 13.2.2)
+        headers.remove(header::IF_MODIFIED_SINCE);
+    }
+    if let Some(mtime) = meta.modified().ok().and_then(unix_secs)
+        && mtime >= unix_secs(SystemTime::now()).unwrap_or_default()
+    {
+        // still being rewritten this second
+        headers.remove(header::IF_MODIFIED_SINCE);
+    }
+
+    let mut resp = next.run(req).await;
+    let success = resp.status().is_success();
+    let headers = resp.headers_mut();
+    if ambiguous_last_modified(headers) {
+        headers.remove(header::LAST_MODIFIED);
+    }
+    if success && let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, value);
+    }
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    resp
+}
+
+/// Changes to size, mtime (nanoseconds) or inode make a new tag; the rename
+/// ffmpeg does replaces the inode even when nothing else differs.
+fn etag(meta: &std::fs::Metadata) -> String {
+    let nanos = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    #[cfg(unix)]
+    let inode = std::os::unix::fs::MetadataExt::ino(meta);
+    #[cfg(not(unix))]
+    let inode = 0u64;
+    format!("\"{:x}-{:x}-{:x}\"", inode, meta.len(), nanos)
+}
+
+/// A `Last-Modified` in the current second could be followed by another
+/// write with the same HTTP date.
+fn ambiguous_last_modified(headers: &HeaderMap) -> bool {
+    let Some(last_modified) = headers
+        .get(header::LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+    else {
+        return false;
+    };
+    unix_secs(last_modified) >= unix_secs(SystemTime::now())
+}
+
+/// Counts requests for `{name}.m3u8` as viewers of that recording, see
+/// `auto_stop_idle_secs`.
+pub async fn track_views(
+    State(manager): State<Arc<RecordingManager>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let file = req.uri().path().rsplit('/').next().unwrap_or_default();
+    if let Some(name) = file.strip_suffix(".m3u8") {
+        manager.touch(name).await;
+    }
+    next.run(req).await
+}
+
+fn unix_secs(t: SystemTime) -> Option<u64> {
+    t.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
@@ -1,29 +1,293 @@
-use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::AtomicBool},
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use axum::{
-    Router,
-    routing::{get, post},
+    Router, middleware,
+    routing::{delete, get, post, put},
 };
 use clap::Parser;
-use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{AllowOrigin, Any, CorsLayer},
+    limit::RequestBodyLimitLayer,
+    services::ServeDir,
+    timeout::TimeoutLayer,
+    trace::TraceLayer,
+};
 use tracing::{Level, error, info};
 
+mod archive;
+mod command;
+mod config;
+mod diskmon;
+mod events;
+mod export;
 mod ffmpeg;
+mod fifo;
 mod handlers;
+mod input;
+mod jobs;
+mod livecache;
+mod meta;
+mod playlist;
+mod probe;
+mod procstat;
+mod queue;
+mod reclog;
 mod recording;
+mod requestid;
+mod retention;
+mod schedule;
+mod selftest;
 mod state;
+#[cfg(test)]
+mod testutil;
 
-use handlers::{finalize, list_finished, list_live, start, stop};
-use recording::start_ffmpeg;
+use config::{Config, SettingsHandle};
+use diskmon::DiskMonitor;
+use handlers::{
+    cancel_job, cancel_queued, channel_recordings, clip, delete_failed, delete_finished, dvr,
+    export_mp4, finalize, finalize_preview, get_config, index, ingest, kill, list_channels,
+    list_failed, list_finished, list_jobs, list_live, list_queue, list_segments, not_found,
+    probe_source, reconfigure, recordings_health, retry_failed, rotate_log, run_selftest,
+    segment_events, set_maintenance, snapshot, start, stats, status, stop, update_tags,
+    wait_playlist,
+};
+use queue::StartQueue;
+use recording::{resume_cleanups, resume_ffmpeg, sanitize_name};
 use state::{AppState, RecordingManager};
 
+/// Maximum accepted request body for API calls
+const API_BODY_LIMIT: usize = 256 * 1024;
+/// Upper bound for mutating API handlers
+const API_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     /// Base directory for DVR files
-    #[arg(long, env = "HTTPLIVE_BASE_DIR", default_value = ".")]
-    base_dir: PathBuf,
+    #[arg(long, env = "HTTPLIVE_BASE_DIR")]
+    base_dir: Option<PathBuf>,
+    /// JSON config file, re-read on SIGHUP
+    #[arg(long, env = "HTTPLIVE_CONFIG")]
+    config: Option<PathBuf>,
+}
+
+fn cors_layer(settings: Arc<SettingsHandle>) -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(move |origin, _| {
+            settings.load().allows_origin(origin)
+        }))
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers([requestid::X_REQUEST_ID.clone()])
+}
+
+/// Serve `router` below `base_path` (e.g. `/dvr` behind a reverse proxy).
+fn mount<S: Clone + Send + Sync + 'static>(base_path: &str, router: Router<S>) -> Router<S> {
+    if base_path.is_empty() {
+        router
+    } else {
+        Router::new().nest(base_path, router)
+    }
+}
+
+/// Bind `path`, replacing a socket file left behind by an unclean
+/// shutdown but not one another process is still listening on.
+#[cfg(unix)]
+async fn bind_unix_socket(path: &std::path::Path) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(md) = tokio::fs::symlink_metadata(path).await {
+        if !md.file_type().is_socket() {
+            anyhow::bail!("{} exists and is not a socket", path.display());
+        }
+        if tokio::net::UnixStream::connect(path).await.is_ok() {
+            anyhow::bail!("{} is in use by another process", path.display());
+        }
+        info!(socket=?path, "removing stale socket");
+        tokio::fs::remove_file(path).await?;
+    }
+    Ok(tokio::net::UnixListener::bind(path)?)
+}
+
+/// Resolves on Ctrl-C or SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Create the directories of a namespace (the server itself, or a tenant
+/// below `tenants/{name}`) and its state. URLs it generates start with
+/// `base_path`.
+async fn open_namespace(
+    root: &Path,
+    config: &Config,
+    base_path: String,
+    settings: Arc<SettingsHandle>,
+) -> Result<AppState> {
+    let pending_dir = root.join("pending_recordings");
+    let finished_dir = root.join("finished_recordings");
+    let logs_dir = root.join("recording_logs");
+    for dir in [&pending_dir, &finished_dir, &logs_dir] {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    Ok(AppState {
+        pending_dir,
+        finished_dir,
+        logs_dir,
+        manager: Arc::new(RecordingManager::new(root.join("active_recordings.json"))),
+        queue: Arc::new(StartQueue::new(root.join("queued_recordings.json"))),
+        config: Arc::new(Config {
+            base_dir: Some(root.to_path_buf()),
+            base_path,
+            ..config.clone()
+        }),
+        settings,
+        snapshots: Arc::new(Default::default()),
+        probes: Arc::new(Default::default()),
+        finished: Arc::new(Default::default()),
+        jobs: Arc::new(Default::default()),
+        disk: Arc::new(DiskMonitor::new()),
+        segment_events: tokio::sync::broadcast::channel(events::CHANNEL_CAPACITY).0,
+        started: Instant::now(),
+        maintenance: Arc::new(AtomicBool::new(false)),
+    })
+}
+
+/// Start the background tasks of a namespace and resume its recordings.
+async fn start_namespace(state: &AppState) -> Result<()> {
+    state.manager.spawn_watchdog();
+    state
+        .disk
+        .spawn(state.pending_dir.clone(), state.settings.clone());
+    retention::spawn(state.clone());
+
+    state.manager.load_failed().await?;
+    resume_cleanups(state).await?;
+    let existing = state.manager.load().await?;
+    for req in existing {
+        if let Err(e) = resume_ffmpeg(state, &req).await {
+            error!(error=?e, name=%req.name, "failed to resume recording");
+        }
+    }
+    // after the resumed recordings, which already had their slots
+    state.queue.load().await?;
+    StartQueue::spawn(state.clone());
+    Ok(())
+}
+
+/// API routes of one namespace, relative to its base path.
+fn api_routes(index_page: bool) -> Router<AppState> {
+    let routes = Router::new()
+        .route(
+            "/api/start",
+            post(start).layer(TimeoutLayer::new(API_TIMEOUT)),
+        )
+        .route(
+            "/api/stop/{name}",
+            post(stop).layer(TimeoutLayer::new(API_TIMEOUT)),
+        )
+        .route(
+            "/api/kill/{name}",
+            post(kill).layer(TimeoutLayer::new(API_TIMEOUT)),
+        )
+        .route(
+            "/api/reconfigure/{name}",
+            post(reconfigure).layer(TimeoutLayer::new(API_TIMEOUT)),
+        )
+        // No timeout: aborting halfway would leave segments split between
+        // pending and finished, and long recordings can take a while to move.
+        .route("/api/finalize/{name}", post(finalize))
+        .route("/api/finalize/{name}/preview", get(finalize_preview))
+        .route("/api/export/{name}", post(export_mp4))
+        .route("/api/live", get(list_live))
+        .route("/api/finished", get(list_finished))
+        .route("/api/channels", get(list_channels))
+        .route("/api/channels/{channel}", get(channel_recordings))
+        // No timeout either: a large archive takes a while to delete
+        .route("/api/finished/delete", post(delete_finished))
+        .route(
+            "/api/finished/{name}/tags",
+            put(update_tags).layer(TimeoutLayer::new(API_TIMEOUT)),
+        )
+        .route("/api/config", get(get_config))
+        .route("/api/snapshot/{file}", get(snapshot))
+        .route("/api/status/{name}", get(status))
+        .route("/api/stats", get(stats))
+        .route("/api/health/recordings", get(recordings_health))
+        .route("/api/events/segments", get(segment_events))
+        .route(
+            "/api/probe",
+            post(probe_source).layer(TimeoutLayer::new(API_TIMEOUT)),
+        )
+        .route("/api/segments/{name}", get(list_segments))
+        .route("/api/playlist/{name}/wait", get(wait_playlist))
+        .route("/api/clip/{file}", get(clip))
+        .route("/api/dvr/{file}", get(dvr))
+        // Takes about ten seconds of real time to record
+        .route("/api/selftest", post(run_selftest))
+        .route("/api/queue", get(list_queue))
+        .route("/api/queue/{name}", delete(cancel_queued))
+        .route(
+            "/api/logs/{name}/rotate",
+            post(rotate_log).layer(TimeoutLayer::new(API_TIMEOUT)),
+        )
+        .route("/api/maintenance", post(set_maintenance))
+        .route("/api/jobs", get(list_jobs))
+        .route("/api/jobs/{id}", delete(cancel_job))
+        .route("/api/failed", get(list_failed))
+        .route("/api/failed/{name}", delete(delete_failed))
+        .route("/api/failed/{name}/retry", post(retry_failed))
+        .layer(RequestBodyLimitLayer::new(API_BODY_LIMIT))
+        // streams of arbitrary length, added after the body limit
+        .route("/api/ingest/{name}", post(ingest));
+    if index_page {
+        routes.route("/", get(index))
+    } else {
+        routes
+    }
+}
+
+/// Live and VOD files of one namespace below its base path.
+fn vod_routes(state: &AppState) -> Router {
+    let base_path = &state.config.base_path;
+    Router::new()
+        .nest_service(
+            &format!("{}/live", base_path),
+            ServeDir::new(state.pending_dir.clone()),
+        )
+        .layer(middleware::from_fn_with_state(
+            Arc::new(state.pending_dir.clone()),
+            livecache::live_playlist_conditional,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.manager.clone(),
+            livecache::track_views,
+        ))
+        .nest_service(
+            &format!("{}/vod", base_path),
+            ServeDir::new(state.finished_dir.clone()),
+        )
 }
 
 #[tokio::main]
@@ -38,61 +302,93 @@ async fn main() -> Result<()> {
         .init();
 
     let args = Cli::parse();
-    let root = if args.base_dir.is_absolute() {
-        args.base_dir
+    let config = match &args.config {
+        Some(path) => Config::load(path).await?,
+        None => Config::default(),
+    };
+    let base_dir = args
+        .base_dir
+        .or_else(|| config.base_dir.clone())
+        .unwrap_or_else(|| PathBuf::from("."));
+    let root = if base_dir.is_absolute() {
+        base_dir
     } else {
-        std::env::current_dir()?.join(args.base_dir)
+        std::env::current_dir()?.join(base_dir)
     };
     tokio::fs::create_dir_all(&root).await?;
-    let pending_dir = root.join("pending_recordings");
-    let finished_dir = root.join("finished_recordings");
-    tokio::fs::create_dir_all(&pending_dir).await?;
-    tokio::fs::create_dir_all(&finished_dir).await?;
-
-    let manager = Arc::new(RecordingManager::new(root.join("active_recordings.json")));
-    let state = AppState {
-        pending_dir: pending_dir.clone(),
-        finished_dir: finished_dir.clone(),
-        manager: manager.clone(),
-    };
+    let settings = Arc::new(SettingsHandle::new(config.settings.clone()));
+    let state = open_namespace(&root, &config, config.base_path.clone(), settings.clone()).await?;
+    let mut tenants = Vec::new();
+    for tenant in &config.tenants {
+        let tenant = sanitize_name(tenant).context("invalid tenant name in config")?;
+        let tenant_root = root.join("tenants").join(&tenant);
+        let base_path = format!("{}/t/{}", config.base_path, tenant);
+        tenants.push(open_namespace(&tenant_root, &config, base_path, settings.clone()).await?);
+    }
+
+    if let Some(path) = &args.config {
+        config::spawn_reload_on_sighup(path.clone(), config.clone(), settings.clone());
+    }
 
     ffmpeg::check_ffmpeg().await?;
     info!("Self test with ffmpeg completed successfully");
 
-    let existing = manager.load().await?;
-    for req in existing {
-        if let Err(e) = start_ffmpeg(&state, &req, true).await {
-            error!(error=?e, name=%req.name, "failed to resume recording");
-        }
+    for ns in std::iter::once(&state).chain(&tenants) {
+        start_namespace(ns).await?;
     }
 
     //
     // API-Server (Steuerung)
     //
-    let api_app = Router::new()
-        .route("/api/start", post(start))
-        .route("/api/stop/{name}", post(stop))
-        .route("/api/finalize/{name}", post(finalize))
-        .route("/api/live", get(list_live))
-        .route("/api/finished", get(list_finished))
-        .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http())
+    let mut api_app = mount(&config.base_path, api_routes(config.index_page))
+        .fallback(not_found)
         .with_state(state.clone());
+    for tenant in &tenants {
+        api_app = api_app.merge(Router::new().nest(
+            &tenant.config.base_path,
+            api_routes(config.index_page).with_state(tenant.clone()),
+        ));
+    }
+    let api_app = api_app
+        .layer(cors_layer(settings.clone()))
+        .layer(TraceLayer::new_for_http().make_span_with(requestid::make_span))
+        .layer(middleware::from_fn(requestid::request_id))
+        // JSON and playlists only; media on the VOD routes is already
+        // compressed. Outermost, so request_id sees the plain error bodies.
+        .layer(CompressionLayer::new());
 
     //
     // VOD/Recording-Server (host only files)
     //
-    let vod_app = Router::new()
-        .nest_service("/live", ServeDir::new(pending_dir))
-        .nest_service("/vod", ServeDir::new(finished_dir))
-        .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http());
+    let vod_app = tenants
+        .iter()
+        .fold(vod_routes(&state), |app, tenant| {
+            app.merge(vod_routes(tenant))
+        })
+        .layer(cors_layer(settings.clone()))
+        .layer(TraceLayer::new_for_http().make_span_with(requestid::make_span))
+        .layer(middleware::from_fn(requestid::request_id));
+
+    #[cfg(not(unix))]
+    if config.bind_uds.is_some() {
+        anyhow::bail!("bind_uds is only supported on Unix");
+    }
+    #[cfg(unix)]
+    if let Some(path) = &config.bind_uds {
+        let listener = bind_unix_socket(path).await?;
+        info!(socket=?path, "API and VOD server listening on Unix socket");
+        let result = axum::serve(listener, api_app.merge(vod_app))
+            .with_graceful_shutdown(shutdown_signal())
+            .await;
+        tokio::fs::remove_file(path).await.ok();
+        return Ok(result?);
+    }
 
     //
     // Listener parallel starten
     //
-    let api_addr: SocketAddr = ([0, 0, 0, 0], 8080).into();
-    let vod_addr: SocketAddr = ([0, 0, 0, 0], 8081).into();
+    let api_addr = config.api_addr;
+    let vod_addr = config.vod_addr;
 
     let api_listener = tokio::net::TcpListener::bind(api_addr).await?;
     let vod_listener = tokio::net::TcpListener::bind(vod_addr).await?;
@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// Per-recording metadata. Lives next to the event playlist as
+/// `{name}.meta.json` while recording and is moved to `{name}/meta.json`
+/// on finalize.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingMeta {
+    pub tags: Vec<String>,
+    /// Groups recordings of the same source, see `/api/channels`
+    pub channel: Option<String>,
+    /// Overrides the global `retention_days` (0 = keep forever)
+    pub retention_days: Option<u32>,
+    /// Audio sidecar file names inside the VOD folder
+    pub audio: Vec<String>,
+    /// Matroska archive file names inside the VOD folder
+    pub archive: Vec<String>,
+    /// Master playlist inside the VOD folder, written when the recording
+    /// has subtitles, more than one audio track or an I-frame playlist
+    pub master: Option<String>,
+    /// I-frame playlist inside the VOD folder, see `iframe_playlist`
+    pub iframes: Option<String>,
+    /// Sum of the `#EXTINF` durations of the VOD playlist, set on finalize
+    pub duration_secs: Option<f64>,
+    /// Audio tracks of the input with `preserve_all_audio`, in stream order
+    pub audio_tracks: Vec<AudioTrack>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioTrack {
+    /// From the stream's `language` tag
+    pub language: Option<String>,
+    /// Media playlist inside the VOD folder; `None` for the first track,
+    /// which is muxed into the main playlist
+    pub playlist: Option<String>,
+}
+
+pub fn pending_path(pending_dir: &Path, name: &str) -> PathBuf {
+    pending_dir.join(format!("{}.meta.json", name))
+}
+
+pub fn finished_path(finished_dir: &Path, name: &str) -> PathBuf {
+    finished_dir.join(name).join("meta.json")
+}
+
+/// Missing or unreadable metadata is treated as empty.
+pub async fn load(path: &Path) -> RecordingMeta {
+    match fs::read_to_string(path).await {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_default(),
+        Err(_) => RecordingMeta::default(),
+    }
+}
+
+pub async fn save(path: &Path, meta: &RecordingMeta) -> Result<()> {
+    let json = serde_json::to_string_pretty(meta)?;
+    fs::write(path, json).await?;
+    Ok(())
+}
+
+pub fn sanitize_tags(tags: &[String]) -> Result<Vec<String>> {
+    let mut out: Vec<String> = Vec::new();
+    for tag in tags {
+        let t = tag.trim();
+        if t.is_empty() || t.len() > 64 || t.chars().any(char::is_control) {
+            anyhow::bail!("invalid tag: {:?}", tag);
+        }
+        if !out.iter().any(|o| o == t) {
+            out.push(t.to_string());
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,498 @@
+use std::fmt;
+
+use serde::Serialize;
+
+/// A parsed HLS media playlist.
+///
+/// Parsing and printing round-trip the playlist structure: playlist-level
+/// tags, every segment with its tags, and comments. Numbers are printed in
+/// ffmpeg's format, so the text itself may differ from hand-written input.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Playlist {
+    /// Playlist-level tags other than the ones below, in order
+    pub header: Vec<String>,
+    /// From `#EXT-X-TARGETDURATION`
+    pub target_duration: Option<u64>,
+    /// From `#EXT-X-PLAYLIST-TYPE` (`EVENT` or `VOD`)
+    pub playlist_type: Option<String>,
+    pub segments: Vec<Segment>,
+    /// Lines after the last segment, e.g. comments
+    pub trailer: Vec<String>,
+    /// Has `#EXT-X-ENDLIST`
+    pub endlist: bool,
+}
+
+/// A media segment as listed in an HLS media playlist.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Segment {
+    pub uri: String,
+    /// From `#EXTINF`
+    pub duration: f64,
+    /// From `#EXT-X-PROGRAM-DATE-TIME`, if present
+    pub program_date_time: Option<String>,
+    /// Preceded by `#EXT-X-DISCONTINUITY`
+    pub discontinuity: bool,
+    /// Other tags and comments before the URI, kept as-is
+    #[serde(skip)]
+    pub tags: Vec<String>,
+}
+
+impl Playlist {
+    pub fn parse(playlist: &str) -> Self {
+        let mut pl = Playlist::default();
+        let mut duration = 0.0;
+        let mut pdt = None;
+        let mut discontinuity = false;
+        let mut tags = Vec::new();
+        for line in playlist.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with("#EXTM3U") {
+                continue;
+            } else if let Some(v) = line.strip_prefix("#EXTINF:") {
+                duration = v
+                    .split(',')
+                    .next()
+                    .and_then(|d| d.trim().parse().ok())
+                    .unwrap_or(0.0);
+            } else if let Some(v) = line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:") {
+                pdt = Some(v.to_string());
+            } else if line == "#EXT-X-DISCONTINUITY" {
+                discontinuity = true;
+            } else if let Some(v) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+                let v = v.trim().parse().ok();
+                pl.target_duration = pl.target_duration.max(v);
+            } else if let Some(v) = line.strip_prefix("#EXT-X-PLAYLIST-TYPE:") {
+                pl.playlist_type = Some(v.trim().to_string());
+            } else if line == "#EXT-X-ENDLIST" {
+                pl.endlist = true;
+            } else if is_playlist_tag(line) {
+                // appended runs repeat the header; keep the first of each tag
+                let name = line.split(':').next().unwrap_or(line);
+                if !pl.header.iter().any(|h| h.split(':').next() == Some(name)) {
+                    pl.header.push(line.to_string());
+                }
+            } else if line.starts_with('#') {
+                tags.push(line.to_string());
+            } else {
+                pl.segments.push(Segment {
+                    uri: line.to_string(),
+                    duration,
+                    program_date_time: pdt.take(),
+                    discontinuity,
+                    tags: std::mem::take(&mut tags),
+                });
+                duration = 0.0;
+                discontinuity = false;
+            }
+        }
+        pl.trailer = tags;
+        pl
+    }
+}
+
+impl fmt::Display for Playlist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "#EXTM3U")?;
+        for tag in &self.header {
+            writeln!(f, "{}", tag)?;
+        }
+        if let Some(t) = self.target_duration {
+            writeln!(f, "#EXT-X-TARGETDURATION:{}", t)?;
+        }
+        if let Some(t) = &self.playlist_type {
+            writeln!(f, "#EXT-X-PLAYLIST-TYPE:{}", t)?;
+        }
+        for seg in &self.segments {
+            if seg.discontinuity {
+                writeln!(f, "#EXT-X-DISCONTINUITY")?;
+            }
+            for tag in &seg.tags {
+                writeln!(f, "{}", tag)?;
+            }
+            if let Some(pdt) = &seg.program_date_time {
+                writeln!(f, "#EXT-X-PROGRAM-DATE-TIME:{}", pdt)?;
+            }
+            writeln!(f, "#EXTINF:{:.6},", seg.duration)?;
+            writeln!(f, "{}", seg.uri)?;
+        }
+        for line in &self.trailer {
+            writeln!(f, "{}", line)?;
+        }
+        if self.endlist {
+            writeln!(f, "#EXT-X-ENDLIST")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the segments of a media playlist in playlist order.
+pub fn parse_segments(playlist: &str) -> Vec<Segment> {
+    Playlist::parse(playlist).segments
+}
+
+/// Tags that describe the whole playlist rather than the following segment.
+pub fn is_playlist_tag(line: &str) -> bool {
+    [
+        "#EXT-X-VERSION",
+        "#EXT-X-TARGETDURATION",
+        "#EXT-X-MEDIA-SEQUENCE",
+        "#EXT-X-DISCONTINUITY-SEQUENCE",
+        "#EXT-X-INDEPENDENT-SEGMENTS",
+        "#EXT-X-I-FRAMES-ONLY",
+        "#EXT-X-START",
+        "#EXT-X-PLAYLIST-TYPE",
+        "#EXT-X-ENDLIST",
+    ]
+    .iter()
+    .any(|t| line.starts_with(t))
+}
+
+/// Ad-break markers (SCTE-35 signalling as packagers write it). They belong
+/// to the segment they precede and have to survive trims and clips.
+pub fn is_ad_marker(tag: &str) -> bool {
+    [
+        "#EXT-X-CUE-OUT",
+        "#EXT-X-CUE-IN",
+        "#EXT-X-DATERANGE",
+        "#EXT-X-SCTE35",
+        "#EXT-OATCLS-SCTE35",
+    ]
+    .iter()
+    .any(|t| tag.starts_with(t))
+}
+
+fn is_cue_out(tag: &str) -> bool {
+    tag.starts_with("#EXT-X-CUE-OUT") && !tag.starts_with("#EXT-X-CUE-OUT-CONT")
+}
+
+fn is_cue_in(tag: &str) -> bool {
+    tag.starts_with("#EXT-X-CUE-IN")
+}
+
+/// The `CUE-OUT` of the ad break still open after `segments`, if any.
+fn open_cue_out(segments: &[Segment]) -> Option<&str> {
+    let mut open = None;
+    for tag in segments.iter().flat_map(|s| &s.tags) {
+        if is_cue_out(tag) {
+            open = Some(tag.as_str());
+        } else if is_cue_in(tag) {
+            open = None;
+        }
+    }
+    open
+}
+
+/// Markers to put before `segments[first]` when the segments before it are
+/// cut off: their `DATERANGE`s (players place them by `START-DATE`) and the
+/// `CUE-OUT` of a break that is still running, unless `segments[first]`
+/// starts or ends a break itself.
+pub fn carried_markers(segments: &[Segment], first: usize) -> Vec<String> {
+    let Some(seg) = segments.get(first) else {
+        return Vec::new();
+    };
+    let mut markers: Vec<String> = segments[..first]
+        .iter()
+        .flat_map(|s| &s.tags)
+        .filter(|t| t.starts_with("#EXT-X-DATERANGE"))
+        .cloned()
+        .collect();
+    if let Some(cue_out) = open_cue_out(&segments[..first])
+        && !seg.tags.iter().any(|t| is_cue_out(t) || is_cue_in(t))
+    {
+        markers.push(cue_out.to_string());
+    }
+    markers
+}
+
+/// The `CUE-IN` that ends a break still open after `segments[last]`, taken
+/// from the segments cut off after it (or `trailer`).
+pub fn closing_marker(segments: &[Segment], last: usize, trailer: &[String]) -> Option<String> {
+    open_cue_out(segments.get(..=last)?)?;
+    segments[last + 1..]
+        .iter()
+        .flat_map(|s| &s.tags)
+        .chain(trailer)
+        .find(|t| is_cue_in(t))
+        .cloned()
+}
+
+/// The path part of a segment URI, without `?query` or `#fragment`
+/// (some packagers append access tokens).
+pub fn uri_path(uri: &str) -> &str {
+    uri.split(['?', '#']).next().unwrap_or(uri)
+}
+
+/// WebVTT segments belong to a subtitle rendition, not the media stream.
+pub fn is_subtitle_uri(uri: &str) -> bool {
+    uri_path(uri).to_ascii_lowercase().ends_with(".vtt")
+}
+
+/// Wall-clock time of `segments[index]`: its own `PROGRAM-DATE-TIME`, or the
+/// closest earlier one advanced by the durations in between.
+pub fn program_date_time_at(segments: &[Segment], index: usize) -> Option<String> {
+    let seg = segments.get(index)?;
+    if seg.program_date_time.is_some() {
+        return seg.program_date_time.clone();
+    }
+    let (base_idx, base) = segments[..index]
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(i, s)| s.program_date_time.as_deref().map(|p| (i, p)))?;
+    let (millis, offset_min) = parse_date_time(base)?;
+    let elapsed: f64 = segments[base_idx..index].iter().map(|s| s.duration).sum();
+    Some(format_date_time(
+        millis + (elapsed * 1000.0).round() as i64,
+        offset_min,
+    ))
+}
+
+/// A VOD playlist with only the segments overlapping `start..start+duration`
+/// (seconds), each URI prefixed with `base_url`. Segments are never cut, so
+/// `EXT-X-START` points players at the exact start inside the first one.
+/// Ad markers stay with their segments, see `carried_markers`. `None` if
+/// the window lies outside the playlist.
+pub fn clip_playlist(
+    segments: &[Segment],
+    start: f64,
+    duration: f64,
+    base_url: &str,
+) -> Option<String> {
+    let end = start + duration;
+    let mut t = 0.0;
+    let mut first = None;
+    let mut offset = 0.0;
+    let mut clip = Vec::new();
+    for (i, seg) in segments.iter().enumerate() {
+        if t + seg.duration > start && t < end {
+            if first.is_none() {
+                first = Some(i);
+                offset = start - t;
+            }
+            clip.push(seg);
+        }
+        t += seg.duration;
+    }
+    let first = first?;
+    let last = first + clip.len() - 1;
+
+    let target = clip
+        .iter()
+        .map(|s| s.duration.ceil() as u64)
+        .max()
+        .unwrap_or(1);
+    let mut out = format!(
+        "#EXTM3U\n#EXT-X-VERSION:6\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:0\n\
+         #EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-START:TIME-OFFSET={:.3},PRECISE=YES\n",
+        target, offset
+    );
+    for (i, seg) in clip.iter().enumerate() {
+        if seg.discontinuity && i > 0 {
+            out.push_str("#EXT-X-DISCONTINUITY\n");
+        }
+        let pdt = if i == 0 {
+            program_date_time_at(segments, first)
+        } else {
+            seg.program_date_time.clone()
+        };
+        let markers = if i == 0 {
+            carried_markers(segments, first)
+        } else {
+            Vec::new()
+        };
+        for tag in markers
+            .iter()
+            .chain(seg.tags.iter().filter(|t| is_ad_marker(t)))
+        {
+            out.push_str(tag);
+            out.push('\n');
+        }
+        if let Some(pdt) = pdt {
+            out.push_str(&format!("#EXT-X-PROGRAM-DATE-TIME:{}\n", pdt));
+        }
+        let path = uri_path(&seg.uri);
+        let file = path.rsplit('/').next().unwrap_or(path);
+        out.push_str(&format!(
+            "#EXTINF:{:.6},\n{}/{}\n",
+            seg.duration, base_url, file
+        ));
+    }
+    if let Some(cue_in) = closing_marker(segments, last, &[]) {
+        out.push_str(&cue_in);
+        out.push('\n');
+    }
+    out.push_str("#EXT-X-ENDLIST\n");
+    Some(out)
+}
+
+/// A sliding live playlist with the newest segments covering `window`
+/// seconds, each URI prefixed with `base_url`. Media and discontinuity
+/// sequence numbers count from the start of the recording so players can
+/// follow the window as it moves.
+pub fn dvr_window_playlist(playlist: &Playlist, window: f64, base_url: &str) -> Playlist {
+    let segments = &playlist.segments;
+    let mut first = segments.len();
+    let mut covered = 0.0;
+    while first > 0 && covered < window {
+        first -= 1;
+        covered += segments[first].duration;
+    }
+    let discontinuities = segments[..first].iter().filter(|s| s.discontinuity).count();
+
+    let mut header: Vec<String> = playlist
+        .header
+        .iter()
+        .filter(|h| {
+            !h.starts_with("#EXT-X-MEDIA-SEQUENCE")
+                && !h.starts_with("#EXT-X-DISCONTINUITY-SEQUENCE")
+        })
+        .cloned()
+        .collect();
+    header.push(format!("#EXT-X-MEDIA-SEQUENCE:{}", first));
+    header.push(format!("#EXT-X-DISCONTINUITY-SEQUENCE:{}", discontinuities));
+
+    let mut window_segments = segments[first..].to_vec();
+    if let Some(seg) = window_segments.first_mut() {
+        seg.program_date_time = program_date_time_at(segments, first);
+    }
+    for seg in &mut window_segments {
+        seg.uri = format!("{}/{}", base_url, seg.uri);
+    }
+    Playlist {
+        header,
+        target_duration: playlist.target_duration,
+        // segments drop out of the window, which EVENT does not allow
+        playlist_type: None,
+        segments: window_segments,
+        trailer: Vec::new(),
+        endlist: playlist.endlist,
+    }
+}
+
+/// Parse `YYYY-MM-DDTHH:MM:SS[.fff](Z|+HH:MM|+HHMM)` into UTC epoch
+/// milliseconds and the UTC offset in minutes.
+fn parse_date_time(s: &str) -> Option<(i64, i32)> {
+    let (date, time) = s.split_once('T')?;
+    let mut d = date.splitn(3, '-');
+    let year: i64 = d.next()?.parse().ok()?;
+    let month: u32 = d.next()?.parse().ok()?;
+    let day: u32 = d.next()?.parse().ok()?;
+
+    let tz_pos = time.find(['Z', 'z', '+', '-']).unwrap_or(time.len());
+    let (clock, tz) = time.split_at(tz_pos);
+    let mut c = clock.splitn(3, ':');
+    let hour: i64 = c.next()?.parse().ok()?;
+    let minute: i64 = c.next()?.parse().ok()?;
+    let secs: f64 = c.next()?.parse().ok()?;
+
+    let offset_min = match tz {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let sign = if tz.starts_with('-') { -1 } else { 1 };
+            let digits: String = tz[1..].chars().filter(|c| *c != ':').collect();
+            if digits.len() != 4 {
+                return None;
+            }
+            let h: i32 = digits[..2].parse().ok()?;
+            let m: i32 = digits[2..].parse().ok()?;
+            sign * (h * 60 + m)
+        }
+    };
+
+    let days = days_from_civil(year, month, day);
+    let local_ms = ((days * 24 + hour) * 60 + minute) * 60_000 + (secs * 1000.0).round() as i64;
+    Some((local_ms - offset_min as i64 * 60_000, offset_min))
+}
+
+/// Format like ffmpeg does: `2024-01-31T12:00:00.000+0100`.
+fn format_date_time(utc_ms: i64, offset_min: i32) -> String {
+    let local_ms = utc_ms + offset_min as i64 * 60_000;
+    let days = local_ms.div_euclid(86_400_000);
+    let ms_of_day = local_ms.rem_euclid(86_400_000);
+    let (y, m, d) = civil_from_days(days);
+    let sign = if offset_min < 0 { '-' } else { '+' };
+    let off = offset_min.abs();
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}{}{:02}{:02}",
+        y,
+        m,
+        d,
+        ms_of_day / 3_600_000,
+        ms_of_day / 60_000 % 60,
+        ms_of_day / 1000 % 60,
+        ms_of_day % 1000,
+        sign,
+        off / 60,
+        off % 60
+    )
+}
+
+// Howard Hinnant's days_from_civil / civil_from_days
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_date_times_discontinuities_and_comments() {
+        let text = "#EXTM3U
+#EXT-X-VERSION:3
+#EXT-X-MEDIA-SEQUENCE:0
+#EXT-X-TARGETDURATION:6
+#EXT-X-PLAYLIST-TYPE:EVENT
+#EXT-X-PROGRAM-DATE-TIME:2024-01-31T12:00:00.000+0100
+#EXTINF:6.000000,
+seg_000.ts
+# resumed after restart
+#EXT-X-DISCONTINUITY
+#EXT-X-PROGRAM-DATE-TIME:2024-01-31T12:05:00.000+0100
+#EXTINF:5.500000,
+seg_001.ts
+#EXTINF:6.000000,
+seg_002.ts?token=abc
+# trailing comment
+#EXT-X-ENDLIST
+";
+        let pl = Playlist::parse(text);
+        assert_eq!(pl.segments.len(), 3);
+        assert!(pl.segments[1].discontinuity);
+        assert_eq!(pl.segments[1].tags, ["# resumed after restart"]);
+        assert_eq!(
+            pl.segments[1].program_date_time.as_deref(),
+            Some("2024-01-31T12:05:00.000+0100")
+        );
+        assert_eq!(pl.trailer, ["# trailing comment"]);
+        assert!(pl.endlist);
+
+        let printed = pl.to_string();
+        assert_eq!(Playlist::parse(&printed), pl);
+        // ffmpeg's own output is printed unchanged, apart from the
+        // discontinuity moving in front of the segment's other tags
+        let expected = text.replace(
+            "# resumed after restart\n#EXT-X-DISCONTINUITY\n",
+            "#EXT-X-DISCONTINUITY\n# resumed after restart\n",
+        );
+        assert_eq!(printed, expected);
+    }
+}
@@ -0,0 +1,41 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::{ffmpeg::ffprobe, input, state::AppState};
+
+/// Probe `input_url` with ffprobe, reusing a result younger than the
+/// configured `probe_cache_secs` unless `refresh` is set. Neither the
+/// result nor errors contain the values of its `${VAR}` placeholders.
+pub async fn probe_input(state: &AppState, input_url: &str, refresh: bool) -> Result<Value> {
+    let url = input::expand_env(input_url)?;
+    if let Some(path) = input::local_path(&url) {
+        input::check_local_input(state, &path).await?;
+    }
+
+    let ttl = Duration::from_secs(state.settings.load().probe_cache_secs);
+    if !refresh
+        && let Some((taken, info)) = state.probes.lock().await.get(input_url)
+        && taken.elapsed() < ttl
+    {
+        return Ok(info.clone());
+    }
+
+    // ffprobe's errors and `format.filename` repeat the URL
+    let mut info = ffprobe(&url).await.map_err(|e| {
+        anyhow::anyhow!(input::redact(
+            &format!("{:#}", e),
+            &input::secrets(input_url)
+        ))
+    })?;
+    if let Some(format) = info.get_mut("format").and_then(Value::as_object_mut) {
+        format.insert("filename".into(), input_url.into());
+    }
+    let mut cache = state.probes.lock().await;
+    cache.retain(|_, (taken, _)| taken.elapsed() < ttl);
+    if !ttl.is_zero() {
+        cache.insert(input_url.to_string(), (Instant::now(), info.clone()));
+    }
+    Ok(info)
+}
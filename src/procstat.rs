@@ -0,0 +1,95 @@
+use anyhow::Result;
+use serde::Serialize;
+
+/// Resource usage of a running process.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct ProcessUsage {
+    /// User + system CPU time
+    pub cpu_time_secs: Option<f64>,
+    /// Resident set size
+    pub rss_bytes: Option<u64>,
+}
+
+/// Read usage from `/proc/{pid}`. Returns `None` when the process is gone
+/// (e.g. ffmpeg is being restarted).
+#[cfg(target_os = "linux")]
+pub async fn process_usage(pid: u32) -> Option<ProcessUsage> {
+    // USER_HZ is fixed at 100 for the /proc ABI
+    const TICKS_PER_SEC: f64 = 100.0;
+
+    let stat = tokio::fs::read_to_string(format!("/proc/{}/stat", pid))
+        .await
+        .ok()?;
+    // comm may contain spaces, so start after its closing paren (field 2)
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+
+    let status = tokio::fs::read_to_string(format!("/proc/{}/status", pid))
+        .await
+        .ok()?;
+    let rss_kb = status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok());
+
+    Some(ProcessUsage {
+        cpu_time_secs: Some((utime + stime) as f64 / TICKS_PER_SEC),
+        rss_bytes: rss_kb.map(|kb| kb * 1024),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub async fn process_usage(_pid: u32) -> Option<ProcessUsage> {
+    Some(ProcessUsage::default())
+}
+
+/// Send SIGTERM to `pid`; ffmpeg then finishes its outputs and exits.
+#[cfg(unix)]
+pub async fn terminate(pid: u32) -> Result<()> {
+    let status = tokio::process::Command::new("kill")
+        .args(["-TERM", &pid.to_string()])
+        .status()
+        .await?;
+    if !status.success() {
+        anyhow::bail!("kill -TERM {} failed", pid);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn terminate(pid: u32) -> Result<()> {
+    let status = tokio::process::Command::new("taskkill")
+        .args(["/PID", &pid.to_string()])
+        .status()
+        .await?;
+    if !status.success() {
+        anyhow::bail!("taskkill /PID {} failed", pid);
+    }
+    Ok(())
+}
+
+/// Send SIGKILL to `pid`, without giving it a chance to clean up.
+#[cfg(unix)]
+pub async fn force_kill(pid: u32) -> Result<()> {
+    let status = tokio::process::Command::new("kill")
+        .args(["-KILL", &pid.to_string()])
+        .status()
+        .await?;
+    if !status.success() {
+        anyhow::bail!("kill -KILL {} failed", pid);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn force_kill(pid: u32) -> Result<()> {
+    let status = tokio::process::Command::new("taskkill")
+        .args(["/F", "/PID", &pid.to_string()])
+        .status()
+        .await?;
+    if !status.success() {
+        anyhow::bail!("taskkill /F /PID {} failed", pid);
+    }
+    Ok(())
+}
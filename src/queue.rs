@@ -0,0 +1,193 @@
+//! Starts waiting for a free slot.
+//!
+//! A start with `queue_if_full` that hits `max_concurrent` is put into this
+//! queue instead of being rejected. Higher `priority` goes first, equal
+//! priorities in arrival order. The queue is persisted next to the active
+//! recordings so it survives a restart.
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    sync::Mutex,
+    time::{Duration, interval},
+};
+use tracing::{error, info};
+
+use crate::{
+    recording::{StartReq, start_ffmpeg},
+    state::{AppState, AtCapacity, WriterBusy, unix_now},
+};
+
+/// How often free slots are looked for
+const DRAIN_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QueuedStart {
+    pub req: StartReq,
+    /// Unix time the start was queued
+    pub queued_at: u64,
+    /// Arrival order, breaks ties between equal priorities
+    seq: u64,
+}
+
+#[derive(Serialize)]
+pub struct QueueItem {
+    pub name: String,
+    pub priority: i32,
+    /// 1 = starts next
+    pub position: usize,
+    pub queued_at: u64,
+}
+
+pub struct StartQueue {
+    entries: Mutex<Vec<QueuedStart>>,
+    next_seq: AtomicU64,
+    path: PathBuf,
+}
+
+impl StartQueue {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            entries: Mutex::new(Vec::new()),
+            next_seq: AtomicU64::new(0),
+            path,
+        }
+    }
+
+    /// Restore the queue of a previous server run.
+    pub async fn load(&self) -> Result<()> {
+        if let Ok(content) = fs::read_to_string(&self.path).await {
+            let entries: Vec<QueuedStart> = serde_json::from_str(&content)?;
+            let next = entries.iter().map(|e| e.seq + 1).max().unwrap_or(0);
+            self.next_seq.store(next, Ordering::Relaxed);
+            *self.entries.lock().await = entries;
+        }
+        Ok(())
+    }
+
+    async fn save(&self, entries: &[QueuedStart]) {
+        let result = async {
+            fs::write(&self.path, serde_json::to_string(entries)?).await?;
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            error!(file=?self.path, error=?e, "failed to save start queue");
+        }
+    }
+
+    /// Queue a start; returns its position (1 = next).
+    pub async fn push(&self, req: StartReq) -> Result<usize> {
+        let mut entries = self.entries.lock().await;
+        if entries.iter().any(|e| e.req.name == req.name) {
+            anyhow::bail!("Recording '{}' is already queued", req.name);
+        }
+        let entry = QueuedStart {
+            req,
+            queued_at: unix_now(),
+            seq: self.next_seq.fetch_add(1, Ordering::Relaxed),
+        };
+        let pos = insert(&mut entries, entry);
+        self.save(&entries).await;
+        Ok(pos + 1)
+    }
+
+    /// Put back an entry taken with `pop` at its previous position.
+    async fn requeue(&self, entry: QueuedStart) {
+        let mut entries = self.entries.lock().await;
+        insert(&mut entries, entry);
+        self.save(&entries).await;
+    }
+
+    async fn pop(&self) -> Option<QueuedStart> {
+        let mut entries = self.entries.lock().await;
+        if entries.is_empty() {
+            return None;
+        }
+        let entry = entries.remove(0);
+        self.save(&entries).await;
+        Some(entry)
+    }
+
+    /// Cancel a queued start; returns `false` if there was none.
+    pub async fn remove(&self, name: &str) -> bool {
+        let mut entries = self.entries.lock().await;
+        let Some(i) = entries.iter().position(|e| e.req.name == name) else {
+            return false;
+        };
+        entries.remove(i);
+        self.save(&entries).await;
+        true
+    }
+
+    pub async fn contains(&self, name: &str) -> bool {
+        self.entries.lock().await.iter().any(|e| e.req.name == name)
+    }
+
+    /// Queued starts in the order they will be started.
+    pub async fn list(&self) -> Vec<QueueItem> {
+        self.entries
+            .lock()
+            .await
+            .iter()
+            .enumerate()
+            .map(|(i, e)| QueueItem {
+                name: e.req.name.clone(),
+                priority: e.req.priority,
+                position: i + 1,
+                queued_at: e.queued_at,
+            })
+            .collect()
+    }
+
+    /// Periodically start queued recordings while slots are free.
+    pub fn spawn(state: AppState) {
+        tokio::spawn(async move {
+            let mut tick = interval(DRAIN_INTERVAL);
+            loop {
+                tick.tick().await;
+                drain(&state).await;
+            }
+        });
+    }
+}
+
+/// Keeps `entries` sorted by priority (highest first), then arrival.
+fn insert(entries: &mut Vec<QueuedStart>, entry: QueuedStart) -> usize {
+    let key = |e: &QueuedStart| (std::cmp::Reverse(e.req.priority), e.seq);
+    let pos = entries.partition_point(|e| key(e) < key(&entry));
+    entries.insert(pos, entry);
+    pos
+}
+
+async fn drain(state: &AppState) {
+    loop {
+        if state.in_maintenance() {
+            return;
+        }
+        let max_concurrent = state.settings.load().max_concurrent;
+        if max_concurrent > 0 && state.manager.running_count().await >= max_concurrent {
+            return;
+        }
+        let Some(entry) = state.queue.pop().await else {
+            return;
+        };
+        let name = entry.req.name.clone();
+        match start_ffmpeg(state, &entry.req, entry.req.resume).await {
+            Ok(_) => info!(%name, "queued recording started"),
+            // a direct start took the slot first, or the previous run of
+            // this name is still shutting down
+            Err(e) if e.is::<AtCapacity>() || e.is::<WriterBusy>() => {
+                state.queue.requeue(entry).await;
+                return;
+            }
+            Err(e) => error!(%name, error=?e, "queued recording could not be started"),
+        }
+    }
+}
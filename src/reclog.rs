@@ -0,0 +1,81 @@
+//! Per-recording ffmpeg logs.
+//!
+//! Everything ffmpeg prints for a recording is appended to
+//! `recording_logs/{name}.log`. Once the file would grow past
+//! `log_max_bytes` it is rotated to `{name}.log.1` (older ones shift to
+//! `.2` and so on, `log_keep` are kept), so a source that reconnects all
+//! day cannot fill the disk.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::warn;
+
+use crate::config::SettingsHandle;
+
+pub fn path(logs_dir: &Path, name: &str) -> PathBuf {
+    logs_dir.join(format!("{}.log", name))
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(format!(".{}", n));
+    PathBuf::from(p)
+}
+
+/// Move `path` to `path.1`, shifting older files up and dropping the one
+/// beyond `keep`. With `keep` 0 the log is simply deleted.
+pub async fn rotate(path: &Path, keep: usize) -> Result<()> {
+    if keep == 0 {
+        fs::remove_file(path).await?;
+        return Ok(());
+    }
+    fs::remove_file(rotated(path, keep)).await.ok();
+    for n in (1..keep).rev() {
+        fs::rename(rotated(path, n), rotated(path, n + 1))
+            .await
+            .ok();
+    }
+    fs::rename(path, rotated(path, 1)).await?;
+    Ok(())
+}
+
+/// Appends to a recording's log. The file is opened for every write, so a
+/// rotation from elsewhere (`/api/logs/{name}/rotate`) takes effect at once.
+pub struct RecordingLog {
+    path: PathBuf,
+    settings: Arc<SettingsHandle>,
+}
+
+impl RecordingLog {
+    pub fn new(path: PathBuf, settings: Arc<SettingsHandle>) -> Self {
+        Self { path, settings }
+    }
+
+    pub async fn write(&self, data: &[u8]) {
+        if let Err(e) = self.try_write(data).await {
+            warn!(file=?self.path, error=?e, "failed to write recording log");
+        }
+    }
+
+    async fn try_write(&self, data: &[u8]) -> Result<()> {
+        let settings = self.settings.load();
+        if settings.log_max_bytes > 0
+            && let Ok(md) = fs::metadata(&self.path).await
+            && md.len() + data.len() as u64 > settings.log_max_bytes
+        {
+            rotate(&self.path, settings.log_keep).await?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(data).await?;
+        Ok(())
+    }
+}
@@ -1,16 +1,41 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
 use anyhow::{Context, Result};
+use futures_util::{StreamExt, stream};
 use serde::{Deserialize, Serialize};
 use tokio::{
     fs,
-    process::Command,
-    sync::oneshot,
-    time::{Duration, sleep},
+    process::Child,
+    sync::oneshot::{self, error::TryRecvError},
+    time::{Duration, Instant, interval, sleep, timeout},
 };
-use tracing::{debug, error, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 
-use crate::state::AppState;
+use crate::{
+    archive,
+    command::{CommandSpec, SidecarOutputs},
+    config::{RestartCooldown, Settings, SettingsHandle},
+    events, ffmpeg, fifo,
+    input::{self, PullOpts},
+    jobs::{Cancelled, JobKind},
+    meta::{self, AudioTrack},
+    playlist::{
+        Playlist, Segment, carried_markers, closing_marker, is_ad_marker, is_playlist_tag,
+        is_subtitle_uri, parse_segments, program_date_time_at, uri_path,
+    },
+    probe, procstat,
+    reclog::{self, RecordingLog},
+    schedule::{self, PauseWindow},
+    state::{AppState, HEARTBEAT_INTERVAL, RecordingManager, unix_now},
+};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct StartReq {
@@ -22,12 +47,275 @@ pub struct StartReq {
     /// When true, continue an existing recording by appending to the current
     /// playlist and segments if they are present on disk.
     pub resume: bool,
+    /// Free-form labels used for filtering finished recordings.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Groups repeated recordings of the same source; finished recordings
+    /// are aggregated per channel by `/api/channels`.
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// Days the finished recording is kept before the retention sweeper
+    /// deletes it; 0 keeps it forever. Unset uses the global setting.
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+    /// Wait in the start queue instead of failing when `max_concurrent`
+    /// recordings are running.
+    #[serde(default)]
+    pub queue_if_full: bool,
+    /// Order in the start queue, higher starts first.
+    #[serde(default)]
+    pub priority: i32,
+    /// Finalize to VOD when the recording ends on its own (input ended, or
+    /// the size limit was reached); not after a stop or a failure.
+    #[serde(default)]
+    pub auto_finalize: bool,
+    /// Make `/api/start` wait until the first segment is written and report
+    /// an immediate ffmpeg failure as an error.
+    #[serde(default)]
+    pub wait_for_first_segment: bool,
+    /// Keep every audio stream of the input as a selectable track; the
+    /// VOD gets a master playlist with an audio group.
+    #[serde(default)]
+    pub preserve_all_audio: bool,
+    /// Additionally write an I-frame-only playlist of the input video for
+    /// trick play; the VOD master playlist references it. Stores the
+    /// video a second time.
+    #[serde(default)]
+    pub iframe_playlist: bool,
+    /// Additionally write the first audio stream to `{name}.aac`, encoded
+    /// to AAC whatever the source codec.
+    #[serde(default)]
+    pub extract_audio: bool,
+    /// How to handle an input without audio. When set, the input is probed
+    /// before starting; unset keeps the command as is.
+    #[serde(default)]
+    pub missing_audio: Option<MissingAudio>,
+    /// Additionally write the first subtitle stream as WebVTT segments.
+    /// Captions embedded in the video (CEA-608/708) only work if the
+    /// demuxer exposes them as a subtitle stream.
+    #[serde(default)]
+    pub extract_subtitles: bool,
+    /// Re-encode instead of stream copy.
+    #[serde(default)]
+    pub transcode: Option<Transcode>,
+    /// What to do when stream copy fails because of the source codecs.
+    #[serde(default)]
+    pub codec_policy: CodecPolicy,
+    /// Image burned into the video; requires `transcode`.
+    #[serde(default)]
+    pub watermark: Option<Watermark>,
+    /// Upper limit for the output frame rate (`-fpsmax`); slower sources
+    /// keep their rate. Requires `transcode`.
+    #[serde(default)]
+    pub max_fps: Option<u32>,
+    /// Additionally write everything to a single Matroska file
+    /// (`{name}.mkv`, `{name}.N.mkv` after restarts) as a fallback for
+    /// gaps in the HLS output.
+    #[serde(default)]
+    pub archive_mkv: bool,
+    /// Stop the recording once its segments take up this many bytes.
+    #[serde(default)]
+    pub max_size_bytes: Option<u64>,
+    /// Keep only the last N segments in the live playlist. Every segment is
+    /// still archived for finalize (see `archive`).
+    #[serde(default)]
+    pub live_window_segments: Option<usize>,
+    /// Write segments to `{name}/` below the pending directory; the live
+    /// playlist references them as `{name}/...` (CDN ingest layout).
+    #[serde(default)]
+    pub segment_subdir: bool,
+    /// Daily windows during which ffmpeg is stopped; it is started again
+    /// (after a discontinuity) when a window ends.
+    #[serde(default)]
+    pub pause_windows: Vec<PauseWindow>,
+    /// Extra ffmpeg options placed before the input, e.g.
+    /// `["-probesize", "50M"]`; limited to an allowlist.
+    #[serde(default)]
+    pub global_options: Vec<String>,
+    /// Demuxer tuning for HLS/DASH inputs.
+    #[serde(default)]
+    pub pull: Option<PullOpts>,
+    /// Start reading the input this many seconds in (`-ss` before `-i`);
+    /// only for seekable inputs such as files and HTTP VODs. With stream
+    /// copy the recording starts at the keyframe before that point.
+    #[serde(default)]
+    pub input_seek_secs: Option<f64>,
+    /// Read at most this many seconds of the input (`-t` before `-i`); the
+    /// recording then ends on its own.
+    #[serde(default)]
+    pub duration_secs: Option<f64>,
+    /// Stop the recording once its live playlist has not been requested
+    /// for this many seconds (counted from the start, checked by the
+    /// watchdog).
+    #[serde(default)]
+    pub auto_stop_idle_secs: Option<u64>,
+    /// Also stream the input as MPEG-TS into this named pipe inside the
+    /// configured `fifo_dir` (created if missing).
+    #[serde(default)]
+    pub fifo_output: Option<PathBuf>,
 }
 
 fn default_hls_time() -> u32 {
     6
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Transcode {
+    #[serde(default = "default_video_codec")]
+    pub video_codec: String,
+    #[serde(default = "default_audio_codec")]
+    pub audio_codec: String,
+    /// e.g. `"3M"`
+    #[serde(default)]
+    pub video_bitrate: Option<String>,
+    /// Encoder preset, e.g. `"veryfast"`
+    #[serde(default)]
+    pub preset: Option<String>,
+}
+
+impl Default for Transcode {
+    fn default() -> Self {
+        Self {
+            video_codec: default_video_codec(),
+            audio_codec: default_audio_codec(),
+            video_bitrate: None,
+            preset: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodecPolicy {
+    /// Keep restarting with stream copy
+    #[default]
+    Copy,
+    /// Switch to transcoding with the default codecs when ffmpeg reports
+    /// that a copied stream does not fit into HLS/MPEG-TS
+    CopyOrTranscode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingAudio {
+    /// Skip the audio outputs (`extract_audio`) with a warning
+    Drop,
+    /// Encode a silent track (`anullsrc`) in place of the missing audio
+    Silence,
+}
+
+fn default_video_codec() -> String {
+    "libx264".to_string()
+}
+
+fn default_audio_codec() -> String {
+    "aac".to_string()
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Watermark {
+    /// Image path relative to the configured assets directory
+    pub path: String,
+    #[serde(default)]
+    pub position: WatermarkPosition,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkPosition {
+    TopLeft,
+    #[default]
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Codec names, presets and bitrates are passed as single ffmpeg arguments;
+/// keep them to plain tokens.
+pub fn check_option_value(what: &str, value: &str) -> Result<()> {
+    if value.is_empty()
+        || value.starts_with('-')
+        || !value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        anyhow::bail!("invalid {}: {}", what, value);
+    }
+    Ok(())
+}
+
+/// Global options that neither add inputs or outputs nor touch files,
+/// and whether they take a value.
+const GLOBAL_OPTIONS: &[(&str, bool)] = &[
+    ("-loglevel", true),
+    ("-v", true),
+    ("-nostats", false),
+    ("-stats", false),
+    ("-hide_banner", false),
+    ("-nostdin", false),
+    ("-fflags", true),
+    ("-analyzeduration", true),
+    ("-probesize", true),
+    ("-fpsprobesize", true),
+    ("-thread_queue_size", true),
+    ("-rtbufsize", true),
+    ("-threads", true),
+    ("-err_detect", true),
+    ("-max_delay", true),
+    ("-copyts", false),
+    ("-start_at_zero", false),
+    ("-avoid_negative_ts", true),
+];
+
+fn check_global_options(options: &[String]) -> Result<()> {
+    let mut args = options.iter();
+    while let Some(opt) = args.next() {
+        let Some((_, takes_value)) = GLOBAL_OPTIONS.iter().find(|(name, _)| name == opt) else {
+            anyhow::bail!("global option not allowed: {}", opt);
+        };
+        if *takes_value {
+            let Some(value) = args.next() else {
+                anyhow::bail!("global option {} needs a value", opt);
+            };
+            // flag sets like "+genpts" or "repeat+info"
+            let rest = value.strip_prefix('+').unwrap_or(value);
+            if !rest.is_empty() && rest.split('+').all(|v| check_option_value(opt, v).is_ok()) {
+                continue;
+            }
+            anyhow::bail!("invalid value for {}: {}", opt, value);
+        }
+    }
+    Ok(())
+}
+
+fn check_transcode(t: &Transcode) -> Result<()> {
+    check_option_value("video_codec", &t.video_codec)?;
+    check_option_value("audio_codec", &t.audio_codec)?;
+    if let Some(b) = &t.video_bitrate {
+        check_option_value("video_bitrate", b)?;
+    }
+    if let Some(p) = &t.preset {
+        check_option_value("preset", p)?;
+    }
+    Ok(())
+}
+
+async fn resolve_watermark(state: &AppState, wm: &Watermark) -> Result<PathBuf> {
+    let Some(assets) = &state.config.assets_dir else {
+        anyhow::bail!("watermark requires an assets_dir to be configured");
+    };
+    let base = fs::canonicalize(assets)
+        .await
+        .with_context(|| format!("failed to canonicalize assets dir {}", assets.display()))?;
+    let image = fs::canonicalize(base.join(&wm.path))
+        .await
+        .with_context(|| format!("watermark image {} not found", wm.path))?;
+    if !image.starts_with(&base) || !fs::metadata(&image).await?.is_file() {
+        anyhow::bail!("invalid watermark image: {}", wm.path);
+    }
+    Ok(image)
+}
+
 pub fn sanitize_name(name: &str) -> Result<String> {
     if name.is_empty()
         || !name
@@ -39,80 +327,438 @@ pub fn sanitize_name(name: &str) -> Result<String> {
     Ok(name.to_string())
 }
 
-pub async fn start_ffmpeg(state: &AppState, req: &StartReq, allow_existing: bool) -> Result<()> {
+/// Checks the configured VOD playlist name. Finalize writes its side
+/// playlists next to it, so their names are taken.
+pub fn sanitize_playlist_name(file: &str) -> Result<()> {
+    if [
+        VOD_MASTER_PLAYLIST,
+        VOD_SUBTITLE_PLAYLIST,
+        VOD_IFRAME_PLAYLIST,
+    ]
+    .contains(&file)
+    {
+        anyhow::bail!("playlist file name {} is reserved for finalize", file);
+    }
+    match file.strip_suffix(".m3u8") {
+        Some(stem) if sanitize_name(stem).is_ok() => Ok(()),
+        _ => anyhow::bail!("invalid playlist file name: {}", file),
+    }
+}
+
+/// Start a recording. The returned receiver gets the end of ffmpeg's
+/// stderr if the first ffmpeg run fails; it is closed once that run
+/// ends normally or is stopped.
+pub async fn start_ffmpeg(
+    state: &AppState,
+    req: &StartReq,
+    allow_existing: bool,
+) -> Result<oneshot::Receiver<String>> {
+    launch(state, req, allow_existing, true).await
+}
+
+/// Continue a recording that was running when the server stopped. It held
+/// a slot before, so `max_concurrent` does not apply.
+pub async fn resume_ffmpeg(state: &AppState, req: &StartReq) -> Result<oneshot::Receiver<String>> {
+    launch(state, req, true, false).await
+}
+
+async fn launch(
+    state: &AppState,
+    req: &StartReq,
+    allow_existing: bool,
+    capped: bool,
+) -> Result<oneshot::Receiver<String>> {
     let name = sanitize_name(&req.name)?;
 
     // If already running: return error
     if state.manager.is_running(&name).await {
         anyhow::bail!("Recording '{}' is already running", name);
     }
+    // held by the recording task until ffmpeg has exited, also on resume
+    let writer = state.manager.claim_writer(&name)?;
 
     // Avoid collisions with existing playlists when creating new jobs via API.
     // Resumed recordings may already have on-disk state; in that case we allow it.
     if !allow_existing {
         let pending_pl = state.pending_dir.join(format!("{}.m3u8", name));
-        let finished_pl = state.finished_dir.join(&name).join("index.m3u8");
-        if fs::metadata(&pending_pl).await.is_ok() || fs::metadata(&finished_pl).await.is_ok() {
+        if fs::metadata(&pending_pl).await.is_ok() || state.find_vod_playlist(&name).await.is_some()
+        {
             anyhow::bail!("Recording '{}' already exists", name);
         }
     }
 
+    let input_url = input::expand_env(&req.input_url)?;
+    if input_url == input::STDIN {
+        if req.preserve_all_audio || req.missing_audio.is_some() {
+            anyhow::bail!(
+                "preserve_all_audio and missing_audio need to probe the input, which is not possible for ingested streams"
+            );
+        }
+    } else if let Some(path) = input::local_path(&input_url) {
+        input::check_local_input(state, &path).await?;
+    }
+    if let Some(pull) = &req.pull {
+        pull.check(&input_url)?;
+    }
+    if let Some(secs) = req.input_seek_secs {
+        if !(secs.is_finite() && secs >= 0.0) {
+            anyhow::bail!("input_seek_secs must be a non-negative number");
+        }
+        if input_url == input::STDIN || !is_seekable(state, &req.input_url).await? {
+            anyhow::bail!("input_seek_secs needs a seekable input (file or VOD playlist)");
+        }
+    }
+    if let Some(secs) = req.duration_secs
+        && !(secs.is_finite() && secs > 0.0)
+    {
+        anyhow::bail!("duration_secs must be a positive number");
+    }
+    // restarts of a seekable input continue where the last run stopped,
+    // which needs a seek position to move on from
+    let seek_from_start = req.input_seek_secs.is_none()
+        && req.duration_secs.is_some()
+        && input_url != input::STDIN
+        && is_seekable(state, &req.input_url).await.unwrap_or(false);
+    if req.auto_stop_idle_secs == Some(0) {
+        anyhow::bail!("auto_stop_idle_secs must be at least 1");
+    }
+
+    check_global_options(&req.global_options)?;
+    if let Some(t) = &req.transcode {
+        check_transcode(t)?;
+    }
+    if req.live_window_segments == Some(0) {
+        anyhow::bail!("live_window_segments must be at least 1");
+    }
+    schedule::check(&req.pause_windows)?;
+    if req.iframe_playlist && req.live_window_segments.is_some() {
+        anyhow::bail!("iframe_playlist cannot be combined with live_window_segments");
+    }
+    if req.segment_subdir {
+        fs::create_dir_all(segment_dir(&state.pending_dir, &name)).await?;
+    }
+    let audio_tracks = if req.preserve_all_audio {
+        if req.live_window_segments.is_some() {
+            anyhow::bail!("preserve_all_audio cannot be combined with live_window_segments");
+        }
+        probe_audio_tracks(state, &req.input_url).await?
+    } else {
+        Vec::new()
+    };
+    let no_audio = match req.missing_audio {
+        Some(policy) if !has_audio(state, &req.input_url).await? => {
+            match policy {
+                MissingAudio::Drop if req.extract_audio => {
+                    warn!(%name, "input has no audio; not extracting audio")
+                }
+                MissingAudio::Drop => {}
+                MissingAudio::Silence => info!(%name, "input has no audio; adding silence"),
+            }
+            Some(policy)
+        }
+        _ => None,
+    };
+    let watermark = match &req.watermark {
+        Some(_) if req.transcode.is_none() => {
+            anyhow::bail!("watermark cannot be used with stream copy; enable transcode")
+        }
+        Some(wm) => Some(resolve_watermark(state, wm).await?),
+        None => None,
+    };
+    match req.max_fps {
+        Some(_) if req.transcode.is_none() => {
+            anyhow::bail!("max_fps cannot be used with stream copy; enable transcode")
+        }
+        Some(0) => anyhow::bail!("max_fps must be at least 1"),
+        _ => {}
+    }
+    let fifo = match &req.fifo_output {
+        Some(path) => Some(fifo::prepare(state, path).await?),
+        None => None,
+    };
+
+    let tags = meta::sanitize_tags(&req.tags)?;
+    if let Some(channel) = &req.channel
+        && sanitize_name(channel).is_err()
+    {
+        anyhow::bail!("invalid channel: {}", channel);
+    }
+    let meta_path = meta::pending_path(&state.pending_dir, &name);
+    let mut rec_meta = meta::load(&meta_path).await;
+    if !tags.is_empty() || !allow_existing {
+        rec_meta.tags = tags.clone();
+    }
+    if req.channel.is_some() || !allow_existing {
+        rec_meta.channel = req.channel.clone();
+    }
+    if req.retention_days.is_some() || !allow_existing {
+        rec_meta.retention_days = req.retention_days;
+    }
+    rec_meta.audio_tracks = audio_tracks.clone();
+
     let playlist_name = name.clone();
-    let input_url = req.input_url.clone();
-    let hls_time = req.hls_time;
-    let pending_dir = state.pending_dir.clone();
     let manager = state.manager.clone();
+    let auto_finalize = req.auto_finalize;
 
-    let (stop_tx, mut stop_rx) = oneshot::channel();
+    let (stop_tx, stop_rx) = oneshot::channel();
+    let (ended_tx, ended_rx) = oneshot::channel::<()>();
     let sanitized_req = StartReq {
         name: name.clone(),
-        input_url: req.input_url.clone(),
-        hls_time: req.hls_time,
-        resume: req.resume,
+        tags,
+        input_seek_secs: req.input_seek_secs.or(seek_from_start.then_some(0.0)),
+        ..req.clone()
+    };
+    let spec = CommandSpec {
+        req: sanitized_req.clone(),
+        input_url,
+        input_format: None,
+        pending_dir: state.pending_dir.clone(),
+        watermark,
+        fifo,
+        audio_tracks: audio_tracks.len(),
+        no_audio,
+    };
+    // 0 = unlimited
+    let max_concurrent = if capped {
+        state.settings.load().max_concurrent
+    } else {
+        0
+    };
+    let run = state
+        .manager
+        .start(sanitized_req, stop_tx, ended_rx, max_concurrent)
+        .await?;
+    // only once registered, so a refused start (at capacity, already
+    // running) leaves the recording's metadata alone
+    if let Err(e) = meta::save(&meta_path, &rec_meta).await {
+        state.manager.finish(&name, run).await;
+        return Err(e);
+    }
+
+    let archiver = match req.live_window_segments {
+        Some(_) => match archive::spawn(&state.pending_dir, &name).await {
+            Ok(handle) => Some(handle),
+            Err(e) => {
+                error!(%name, error=?e, "failed to start segment archiver");
+                None
+            }
+        },
+        None => None,
     };
-    state.manager.start(sanitized_req, stop_tx).await?;
 
+    let watcher = events::spawn(&state.pending_dir, &name, state.segment_events.clone()).await;
+
+    // The supervisor runs in its own task so a panic in it still clears
+    // the manager entry instead of leaving a ghost "running" recording.
+    let (first_failure_tx, first_failure) = oneshot::channel();
+    let supervisor = tokio::spawn(supervise(
+        spec,
+        manager.clone(),
+        state.settings.clone(),
+        run,
+        stop_rx,
+        first_failure_tx,
+        reclog::path(&state.logs_dir, &name),
+    ));
+    let state = state.clone();
     tokio::spawn(async move {
-        loop {
-            let playlist = pending_dir.join(format!("{}.m3u8", playlist_name));
-            let seg_pattern =
-                pending_dir.join(format!("{}_seg_%Y-%m-%d_%H-%M-%S_%03d.ts", playlist_name));
-
-            let mut cmd = Command::new("ffmpeg");
-            cmd.kill_on_drop(true)
-                .arg("-y")
-                //.args(["-rtsp_transport", "tcp"])
-                .arg("-re")
-                .args(["-i", &input_url])
-                .args(["-c", "copy"])
-                .args(["-f", "hls"])
-                .args(["-hls_time", &hls_time.to_string()])
-                .args(["-hls_list_size", "0"])
-                .args(["-hls_playlist_type", "event"])
-                .args([
-                    "-hls_flags",
-                    "append_list+discont_start+program_date_time+temp_file",
-                ])
-                .args(["-strftime", "1"])
-                .args(["-hls_segment_filename", &seg_pattern.to_string_lossy()])
-                .arg(playlist.to_string_lossy().to_string());
-
-            info!("Starting ffmpeg: {}", format_command(&cmd));
-
-            let mut child = match cmd.spawn() {
-                Ok(c) => c,
-                Err(e) => {
-                    error!(error=?e, "ffmpeg could not be started");
-                    break;
+        let result = supervisor.await;
+        watcher.finish().await;
+        if let Some(archiver) = archiver {
+            archiver.finish().await;
+        }
+        match result {
+            Ok(Ok(Ending::Completed)) if auto_finalize => {
+                manager.finish(&playlist_name, run).await;
+                info!(name=%playlist_name, "recording completed - finalizing");
+                if let Err(e) =
+                    finalize_to_vod(&state, &playlist_name, FinalizeOpts::default()).await
+                {
+                    error!(name=%playlist_name, error=?e, "automatic finalize failed");
                 }
-            };
+            }
+            Ok(Ok(_)) => manager.finish(&playlist_name, run).await,
+            Ok(Err(reason)) => manager.fail(&playlist_name, run, &reason).await,
+            Err(e) => {
+                manager
+                    .fail(&playlist_name, run, &format!("recording task died: {}", e))
+                    .await
+            }
+        }
+        // free the name before waiters on `ended` start the next run
+        drop(writer);
+        drop(ended_tx);
+    });
+
+    Ok(first_failure)
+}
+
+async fn has_audio(state: &AppState, input_url: &str) -> Result<bool> {
+    let info = probe::probe_input(state, input_url, false)
+        .await
+        .context("missing_audio needs to probe the input")?;
+    Ok(info["streams"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|s| s["codec_type"] == "audio"))
+}
+
+async fn is_seekable(state: &AppState, input_url: &str) -> Result<bool> {
+    let info = probe::probe_input(state, input_url, false)
+        .await
+        .context("input_seek_secs needs to probe the input")?;
+    Ok(input::is_seekable(&info))
+}
+
+/// Audio streams of the input in stream order, with their language tags.
+async fn probe_audio_tracks(state: &AppState, input_url: &str) -> Result<Vec<AudioTrack>> {
+    let info = probe::probe_input(state, input_url, false)
+        .await
+        .context("preserve_all_audio needs to probe the input")?;
+    let tracks: Vec<AudioTrack> = info["streams"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|s| s["codec_type"] == "audio")
+        .map(|s| AudioTrack {
+            language: s["tags"]["language"].as_str().map(str::to_string),
+            playlist: None,
+        })
+        .collect();
+    if tracks.is_empty() {
+        warn!(input=%input_url, "preserve_all_audio: input has no audio streams");
+    }
+    Ok(tracks)
+}
+
+/// Where segments go with `segment_subdir`.
+pub fn segment_dir(pending_dir: &Path, name: &str) -> PathBuf {
+    pending_dir.join(name)
+}
+
+/// Live playlist of audio track `track` (counted from 0; track 0 is part
+/// of the main playlist).
+pub fn audio_track_playlist(pending_dir: &Path, name: &str, track: usize) -> PathBuf {
+    pending_dir.join(format!("{}.audio{}.m3u8", name, track))
+}
+
+pub fn iframe_playlist(pending_dir: &Path, name: &str) -> PathBuf {
+    pending_dir.join(format!("{}.iframes.m3u8", name))
+}
+
+/// Move `input_seek_secs` and `duration_secs` on by the `secs` one ffmpeg
+/// run read. Returns `false` once the duration is used up.
+fn advance_input(req: &mut StartReq, secs: f64) -> bool {
+    if let Some(seek) = &mut req.input_seek_secs {
+        *seek += secs;
+    }
+    match &mut req.duration_secs {
+        Some(left) => {
+            *left -= secs;
+            *left > 0.0
+        }
+        None => true,
+    }
+}
+
+/// Lines of ffmpeg's stderr reported for a failed first run
+const FAILURE_STDERR_LINES: usize = 10;
+
+/// How a supervised recording ended, unless it failed.
+enum Ending {
+    /// ffmpeg exited cleanly (input ended, `-t`) or the size limit was reached
+    Completed,
+    /// A stop was requested
+    Stopped,
+}
+
+/// Run ffmpeg for a recording, restarting it when it exits with an error,
+/// until it ends normally or a stop is requested.
+async fn supervise(
+    mut spec: CommandSpec,
+    manager: Arc<RecordingManager>,
+    settings: Arc<SettingsHandle>,
+    run: u64,
+    mut stop_rx: oneshot::Receiver<()>,
+    first_failure: oneshot::Sender<String>,
+    log: PathBuf,
+) -> std::result::Result<Ending, String> {
+    let mut first_failure = Some(first_failure);
+    let mut recent_restarts = VecDeque::new();
+    let name = &spec.req.name.clone();
+    let playlist = spec.pending_dir.join(format!("{}.m3u8", name));
+    let mut heartbeat = interval(HEARTBEAT_INTERVAL);
+    let secrets = input::secrets(&spec.req.input_url);
+    loop {
+        while let Some(secs) = schedule::paused_for(&spec.req.pause_windows) {
+            info!(%name, secs, "inside pause window - waiting");
+            let resume = sleep(Duration::from_secs(secs as u64));
+            tokio::pin!(resume);
+            loop {
+                tokio::select! {
+                    _ = &mut stop_rx => return Ok(Ending::Stopped),
+                    _ = &mut resume => break,
+                    _ = heartbeat.tick() => manager.heartbeat(name, run).await,
+                }
+            }
+        }
 
-            let mut restart = false;
+        let mut sidecars = SidecarOutputs::default();
+        if spec.req.extract_audio && spec.no_audio != Some(MissingAudio::Drop) {
+            sidecars.audio = Some(next_sidecar_path(&spec.pending_dir, name, AUDIO_EXT).await);
+        }
+        if spec.req.extract_subtitles {
+            sidecars.subtitles =
+                Some(next_sidecar_path(&spec.pending_dir, name, SUBTITLE_LIST_EXT).await);
+        }
+        if spec.req.archive_mkv {
+            sidecars.archive = Some(next_sidecar_path(&spec.pending_dir, name, ARCHIVE_EXT).await);
+        }
+        let mut cmd = spec.build(&sidecars);
+
+        info!("Starting ffmpeg: {}", spec.display(&cmd));
+        let run_started = Instant::now();
+
+        let mut child = match cmd.spawn() {
+            Ok(c) => c,
+            Err(e) => {
+                error!(error=?e, "ffmpeg could not be started");
+                let reason = format!("ffmpeg could not be started: {}", e);
+                if let Some(tx) = first_failure.take() {
+                    let _ = tx.send(reason.clone());
+                }
+                return Err(reason);
+            }
+        };
+
+        manager.set_pid(name, run, child.id()).await;
+        manager.set_stdin(name, run, child.stdin.take()).await;
+        if let (Some(fifo), Some(stdout)) = (&spec.fifo, child.stdout.take()) {
+            tokio::spawn(fifo::relay(stdout, fifo.clone()));
+        }
+        let stderr = child.stderr.take().map(|e| {
+            tokio::spawn(ffmpeg::tee_stderr(
+                e,
+                RecordingLog::new(log.clone(), settings.clone()),
+                secrets.clone(),
+            ))
+        });
+
+        let pause = sleep(Duration::from_secs(
+            schedule::next_pause_in(&spec.req.pause_windows).map_or(u64::MAX, u64::from),
+        ));
+        tokio::pin!(pause);
+        let mut paused = false;
+        let mut restart = false;
+        let mut completed = false;
+        loop {
             tokio::select! {
                 res = child.wait() => {
                     match res {
                         Ok(status) if status.success() => {
                             // finished normally
+                            completed = true;
                         }
                         Ok(_) => {
                             restart = true;
@@ -121,178 +767,1433 @@ pub async fn start_ffmpeg(state: &AppState, req: &StartReq, allow_existing: bool
                             error!(error=?e, "ffmpeg wait failed");
                         }
                     }
+                    break;
                 }
                 _ = &mut stop_rx => {
-                    let _ = child.start_kill();
-                    let _ = child.wait().await;
+                    stop_child(&mut child).await;
+                    break;
+                }
+                _ = &mut pause => {
+                    info!(%name, "pause window started - stopping ffmpeg");
+                    stop_child(&mut child).await;
+                    paused = true;
+                    break;
+                }
+                _ = heartbeat.tick() => {
+                    manager.heartbeat(name, run).await;
+                    if let Ok(modified) = fs::metadata(&playlist).await.and_then(|m| m.modified()) {
+                        manager.set_last_segment(name, run, modified).await;
+                    }
+                    if size_limit_reached(&spec).await {
+                        stop_child(&mut child).await;
+                        completed = true;
+                        break;
+                    }
                 }
             }
+        }
 
-            if !restart {
-                break;
-            }
+        manager.set_pid(name, run, None).await;
+        manager.set_stdin(name, run, None).await;
+
+        // killed from outside (e.g. /api/kill) after a stop was requested
+        if restart && !matches!(stop_rx.try_recv(), Err(TryRecvError::Empty)) {
+            restart = false;
+        }
+        // `-re` reads in real time, so the next run continues about where
+        // this one stopped instead of seeking back to the start
+        if (paused || restart) && !advance_input(&mut spec.req, run_started.elapsed().as_secs_f64())
+        {
+            return Ok(Ending::Completed);
+        }
+        if paused {
+            continue;
+        }
+        if restart && size_limit_reached(&spec).await {
+            return Ok(Ending::Completed);
+        }
+        if !restart {
+            return Ok(if completed {
+                Ending::Completed
+            } else {
+                Ending::Stopped
+            });
+        }
+        let stderr = match stderr {
+            Some(task) => task.await.unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let tail = stderr[stderr.len().saturating_sub(FAILURE_STDERR_LINES)..].join("\n");
+        manager.set_last_error(name, run, tail.clone()).await;
+        if let Some(tx) = first_failure.take() {
+            let _ = tx.send(tail);
+        }
+        if spec.req.codec_policy == CodecPolicy::CopyOrTranscode
+            && spec.req.transcode.is_none()
+            && ffmpeg::is_codec_incompatibility(&stderr)
+        {
+            warn!(%name, "stream copy not possible - switching to transcoding");
+            spec.req.transcode = Some(Transcode::default());
+        }
+        manager.record_restart(name, run).await;
+        let delay = restart_delay(&settings.load().restart_cooldown, &mut recent_restarts);
+        if delay > RESTART_DELAY {
+            warn!(%name, delay_secs = delay.as_secs(), "source keeps failing - cooling down before the next restart");
+        } else {
             info!("ffmpeg exited - retrying in 3s");
-            sleep(Duration::from_secs(3)).await;
         }
+        let retry = sleep(delay);
+        tokio::pin!(retry);
+        loop {
+            tokio::select! {
+                _ = &mut stop_rx => return Ok(Ending::Stopped),
+                _ = &mut retry => break,
+                _ = heartbeat.tick() => manager.heartbeat(name, run).await,
+            }
+        }
+        manager.heartbeat(name, run).await;
+    }
+}
 
-        manager.finish(&playlist_name).await;
-    });
+const RESTART_DELAY: Duration = Duration::from_secs(3);
+/// How long ffmpeg gets to finish its current segment before it is killed
+const STOP_GRACE: Duration = Duration::from_secs(5);
 
-    Ok(())
+/// Let ffmpeg finish the segment it is writing (the temp file is only
+/// renamed and listed on a clean exit), then make sure it is gone.
+async fn stop_child(child: &mut Child) {
+    if let Some(pid) = child.id()
+        && procstat::terminate(pid).await.is_ok()
+        && timeout(STOP_GRACE, child.wait()).await.is_ok()
+    {
+        return;
+    }
+    let _ = child.start_kill();
+    let _ = child.wait().await;
 }
 
-fn format_command(cmd: &Command) -> String {
-    let mut s = String::from("ffmpeg");
-    for arg in cmd.as_std().get_args() {
-        s.push(' ');
-        s.push_str(&arg.to_string_lossy());
+/// Delay before the next restart: the usual short one, or the cooldown
+/// once more than `max_restarts` happened within `window_secs`.
+fn restart_delay(cooldown: &RestartCooldown, recent: &mut VecDeque<Instant>) -> Duration {
+    let window = Duration::from_secs(cooldown.window_secs);
+    recent.retain(|t| t.elapsed() < window);
+    recent.push_back(Instant::now());
+    if cooldown.max_restarts > 0 && recent.len() > cooldown.max_restarts {
+        // start counting again after the cooldown
+        recent.clear();
+        return Duration::from_secs(cooldown.cooldown_secs).max(RESTART_DELAY);
     }
-    s
+    RESTART_DELAY
 }
 
-pub async fn finalize_to_vod(state: &AppState, name: &str) -> Result<()> {
-    let name = sanitize_name(name)?;
-
-    // 1) stop recording if active
-    let _ = state.manager.stop(&name).await;
+async fn size_limit_reached(spec: &CommandSpec) -> bool {
+    let Some(max) = spec.req.max_size_bytes else {
+        return false;
+    };
+    let used = segment_bytes(&spec.pending_dir, &spec.req.name).await;
+    if used >= max {
+        info!(name=%spec.req.name, used, max, "size limit reached - stopping recording");
+        return true;
+    }
+    false
+}
 
-    // 2) read event playlist
-    let src_pl = state.pending_dir.join(format!("{}.m3u8", name));
-    if !src_pl.exists() {
-        anyhow::bail!("Event playlist does not exist: {}", src_pl.display());
+/// Total size of the `{name}_seg_*` files in `dir`, its segment
+/// subdirectory and its archive. Hard-linked segments are counted once.
+async fn segment_bytes(dir: &Path, name: &str) -> u64 {
+    let prefix = format!("{}_seg_", name);
+    let mut seen = HashSet::new();
+    let mut total = 0;
+    for dir in [
+        dir.to_path_buf(),
+        segment_dir(dir, name),
+        archive::dir_path(dir, name),
+    ] {
+        let Ok(mut rd) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = rd.next_entry().await {
+            let file = entry.file_name().to_string_lossy().to_string();
+            if file.starts_with(&prefix)
+                && seen.insert(file)
+                && let Ok(md) = entry.metadata().await
+            {
+                total += md.len();
+            }
+        }
     }
+    total
+}
 
-    let content = fs::read_to_string(&src_pl).await?;
-    let segments = extract_segment_list(&content);
+const AUDIO_EXT: &str = "aac";
+const ARCHIVE_EXT: &str = "mkv";
+/// Subtitle segment lists written while recording
+const SUBTITLE_LIST_EXT: &str = "vtt.m3u8";
+/// Names of the subtitle files written to the VOD folder
+const VOD_SUBTITLE_PLAYLIST: &str = "subtitles.m3u8";
+const VOD_MASTER_PLAYLIST: &str = "master.m3u8";
+const VOD_IFRAME_PLAYLIST: &str = "iframes.m3u8";
 
-    // 3) prepare destination directory
-    let dst_dir = state.finished_dir.join(&name);
-    let dst_pl = dst_dir.join("index.m3u8");
-    if fs::metadata(&dst_pl).await.is_ok() {
-        anyhow::bail!("Recording '{}' already finalized", name);
-    }
-    fs::create_dir_all(&dst_dir).await?;
-
-    // 4) move segments without duplication and adjust URIs
-    info!(%name, total_segments=segments.len(), "finalizing recording - moving segments");
-    for seg in &segments {
-        let src = normalize_segment_path(&state.pending_dir, seg)?;
-        let dst = dst_dir.join(Path::new(seg).file_name().unwrap());
-        if fs::metadata(&dst).await.is_ok() {
-            debug!(dst=?dst, "segment already moved, skipping");
-            continue;
+/// Audio sidecars are `{name}.aac`, followed by `{name}.N.aac` parts for
+/// every restart so earlier audio is never overwritten.
+pub fn is_audio_file(name: &str, file_name: &str) -> bool {
+    is_sidecar_file(name, file_name, AUDIO_EXT)
+}
+
+/// Sidecar outputs (`{name}.{ext}`, then `{name}.N.{ext}`) get a new file
+/// per ffmpeg run.
+fn is_sidecar_file(name: &str, file_name: &str, ext: &str) -> bool {
+    match file_name
+        .strip_prefix(name)
+        .and_then(|r| r.strip_suffix(ext))
+        .and_then(|r| r.strip_suffix('.'))
+    {
+        Some("") => true,
+        Some(part) => part
+            .strip_prefix('.')
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit())),
+        None => false,
+    }
+}
+
+async fn next_sidecar_path(dir: &Path, name: &str, ext: &str) -> PathBuf {
+    let first = dir.join(format!("{}.{}", name, ext));
+    if fs::metadata(&first).await.is_err() {
+        return first;
+    }
+    let mut n = 1u32;
+    loop {
+        let p = dir.join(format!("{}.{}.{}", name, n, ext));
+        if fs::metadata(&p).await.is_err() {
+            return p;
         }
-        debug!(src=?src, dst=?dst, "moving segment");
-        match fs::rename(&src, &dst).await {
-            Ok(_) => {}
-            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
-                // Different filesystem: try hard link + remove
-                if let Err(e2) = fs::hard_link(&src, &dst).await {
-                    error!(src=?src, dst=?dst, error=?e2, "segment move failed");
-                    anyhow::bail!("Could not move segment: {}", src.display());
-                }
-                fs::remove_file(&src).await.ok();
-            }
-            Err(e) => {
-                if e.kind() == std::io::ErrorKind::NotFound && fs::metadata(&dst).await.is_ok() {
-                    debug!(dst=?dst, "segment already moved, skipping");
-                    continue;
-                }
-                error!(src=?src, dst=?dst, error=?e, "segment move failed");
-                anyhow::bail!("Could not move segment: {}", src.display());
+        n += 1;
+    }
+}
+
+/// Sidecar files of a recording in the order they were written.
+async fn list_sidecar_files(dir: &Path, name: &str, ext: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if let Ok(mut rd) = fs::read_dir(dir).await {
+        while let Ok(Some(entry)) = rd.next_entry().await {
+            if let Some(f) = entry.file_name().to_str()
+                && is_sidecar_file(name, f, ext)
+            {
+                files.push((sidecar_index(name, f), entry.path()));
             }
         }
     }
+    files.sort();
+    files.into_iter().map(|(_, p)| p).collect()
+}
 
-    // 5) rewrite playlist: EVENT -> VOD, basename URIs, ENDLIST
-    let vod = rewrite_playlist_to_vod(&content)?;
-    fs::write(&dst_pl, vod.as_bytes()).await?;
-    info!(playlist=?dst_pl, "VOD playlist written");
+fn sidecar_index(name: &str, file_name: &str) -> u32 {
+    file_name[name.len()..]
+        .split('.')
+        .nth(1)
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Options of a finalize: the part of the recording to keep, in seconds
+/// from its start, and how segments are named in the VOD folder.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct FinalizeOpts {
+    pub trim_start: Option<f64>,
+    pub trim_end: Option<f64>,
+    /// Rename segments to `segment_00001.ts` and so on in playlist order
+    /// instead of keeping ffmpeg's file names
+    #[serde(default)]
+    pub renumber_segments: bool,
+}
 
-    // 6) remove pending playlist to save space
-    if let Err(e) = fs::remove_file(&src_pl).await {
-        error!(file=?src_pl, error=?e, "failed to remove pending playlist");
+impl FinalizeOpts {
+    fn is_set(&self) -> bool {
+        self.trim_start.is_some() || self.trim_end.is_some()
     }
+}
 
-    info!(%name, "recording finalized");
-    Ok(())
+/// Segments kept by a trim (by file name) and the recomputed wall-clock
+/// time of the first one.
+struct TrimWindow {
+    keep: HashSet<String>,
+    first_pdt: Option<String>,
 }
 
-fn extract_segment_list(playlist: &str) -> Vec<String> {
-    // Every non-comment, non-empty line is considered a URI
-    playlist
-        .lines()
-        .map(str::trim)
-        .filter(|l| !l.is_empty() && !l.starts_with('#'))
-        .map(|s| s.to_string())
-        .collect()
+/// Returned when finalizing a recording that already has a VOD playlist.
+#[derive(Debug, thiserror::Error)]
+#[error("recording already finalized")]
+pub struct AlreadyFinalized {
+    /// URL of the existing VOD playlist
+    pub playlist: String,
 }
 
-fn rewrite_playlist_to_vod(original: &str) -> Result<String> {
-    // Keep metadata lines, replace or insert PLAYLIST-TYPE:VOD, add ENDLIST, replace segment URIs with basenames
-    let mut out = String::new();
-    let mut has_header = false;
-    let mut has_type = false;
-    let mut has_endlist = false;
+pub async fn finalize_to_vod(state: &AppState, name: &str, opts: FinalizeOpts) -> Result<()> {
+    let name = sanitize_name(name)?;
+    let job = state.jobs.register(JobKind::Finalize, &name).await;
+    let result = async {
+        // 1) stop recording if active; ffmpeg must have exited so the segment
+        // it was writing is in the playlist
+        let _ = state.manager.stop_and_wait(&name).await;
 
-    for line in original.lines() {
-        let l = line.trim_end();
-        if l.starts_with("#EXTM3U") {
-            has_header = true;
-            out.push_str("#EXTM3U\n");
-            continue;
+        if let Some(playlist) = state.find_vod_playlist(&name).await {
+            return Err(AlreadyFinalized {
+                playlist: state.url(&format!("/vod/{}/{}", name, playlist)),
+            }
+            .into());
         }
-        if l.starts_with("#EXT-X-PLAYLIST-TYPE:") {
-            has_type = true;
-            out.push_str("#EXT-X-PLAYLIST-TYPE:VOD\n");
-            continue;
+
+        let dirs = FinalizeDirs {
+            pending_dir: &state.pending_dir,
+            finished_dir: &state.finished_dir,
+            vod_playlist: &state.config.vod_playlist,
+            settings: &state.settings.load(),
+        };
+        info!(%name, job=job.id, "finalizing recording");
+        let result = finalize_files(&dirs, &name, opts, &job.cancel).await;
+        state.invalidate_finished().await;
+        if result.is_ok() {
+            spawn_cleanup(state.clone(), name.clone());
         }
-        if l.starts_with("#EXT-X-ENDLIST") {
-            has_endlist = true;
+        result
+    }
+    .await;
+    job.finish(&result);
+    result
+}
+
+/// Where `finalize_files` reads from and writes to. Kept separate from
+/// `AppState` so the file handling can run against any pair of directories.
+pub struct FinalizeDirs<'a> {
+    pub pending_dir: &'a Path,
+    pub finished_dir: &'a Path,
+    pub vod_playlist: &'a str,
+    /// Decides which leftover files may be deleted
+    pub settings: &'a Settings,
+}
+
+/// What finalize does with the segments of a recording, worked out from
+/// its pending playlist without touching any files.
+struct FinalizePlan {
+    /// Source playlist: the archive with a live window, else the live one
+    content: String,
+    archived: bool,
+    window: Option<TrimWindow>,
+    /// Segment URIs to move, in playlist order
+    segments: Vec<String>,
+    /// Segments outside the trim window
+    dropped: Vec<String>,
+    /// Current -> new file names with `renumber_segments`
+    renames: Option<HashMap<String, String>>,
+}
+
+impl FinalizePlan {
+    async fn read(pending_dir: &Path, name: &str, opts: FinalizeOpts) -> Result<Self> {
+        let src_pl = pending_dir.join(format!("{}.m3u8", name));
+        if fs::metadata(&src_pl).await.is_err() {
+            anyhow::bail!("Event playlist does not exist: {}", src_pl.display());
         }
-        // Keep other lines (including PROGRAM-DATE-TIME) as-is
-        if l.starts_with('#') {
-            out.push_str(l);
-            out.push('\n');
+
+        // with a live window the live playlist only holds the last segments
+        let archive_pl = archive::playlist_path(pending_dir, name);
+        let archived = fs::metadata(&archive_pl).await.is_ok();
+        let content = if archived {
+            fs::read_to_string(&archive_pl).await?
         } else {
-            // Segment URI -> basename only
-            let base = Path::new(l)
-                .file_name()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_else(|| l.to_string());
-            out.push_str(&base);
-            out.push('\n');
-        }
+            fs::read_to_string(&src_pl).await?
+        };
+        let window = if opts.is_set() {
+            Some(trim_window(&content, opts)?)
+        } else {
+            None
+        };
+        let (segments, dropped): (Vec<String>, Vec<String>) =
+            extract_segment_list(&content).into_iter().partition(|s| {
+                window
+                    .as_ref()
+                    .is_none_or(|w| w.keep.contains(&segment_basename(s)))
+            });
+        let renames = opts.renumber_segments.then(|| renumbered_names(&segments));
+        Ok(Self {
+            content,
+            archived,
+            window,
+            segments,
+            dropped,
+            renames,
+        })
     }
 
-    if !has_header {
-        out = format!("#EXTM3U\n{}", out);
+    /// File name of a segment in the VOD folder.
+    fn vod_name(&self, seg: &str) -> String {
+        let base = segment_basename(seg);
+        self.renames
+            .as_ref()
+            .and_then(|r| r.get(&base).cloned())
+            .unwrap_or(base)
     }
-    if !has_type {
-        out = out.replacen("#EXTM3U\n", "#EXTM3U\n#EXT-X-PLAYLIST-TYPE:VOD\n", 1);
+
+    fn vod_playlist(&self) -> Result<String> {
+        rewrite_playlist_to_vod(&self.content, self.window.as_ref(), self.renames.as_ref())
     }
-    if !has_endlist {
-        out.push_str("#EXT-X-ENDLIST\n");
+}
+
+#[derive(Serialize)]
+pub struct FinalizePreview {
+    /// The VOD playlist finalize would write
+    pub playlist: String,
+    /// Segments finalize would move, in playlist order
+    pub segments: Vec<PreviewMove>,
+    /// Segments outside the trim window, which finalize deletes
+    pub dropped: Vec<String>,
+    /// Problems that would make finalize fail or lose segments
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct PreviewMove {
+    /// URI in the pending playlist
+    pub from: String,
+    /// File name in the VOD folder
+    pub to: String,
+}
+
+/// What `finalize_to_vod` would do with `opts`, without touching any files.
+/// Side playlists (subtitles, audio tracks, I-frames) are not previewed.
+pub async fn preview_finalize(
+    state: &AppState,
+    name: &str,
+    opts: FinalizeOpts,
+) -> Result<FinalizePreview> {
+    let name = sanitize_name(name)?;
+    if let Some(playlist) = state.find_vod_playlist(&name).await {
+        return Err(AlreadyFinalized {
+            playlist: state.url(&format!("/vod/{}/{}", name, playlist)),
+        }
+        .into());
+    }
+    let plan = FinalizePlan::read(&state.pending_dir, &name, opts).await?;
+    let playlist = plan.vod_playlist()?;
+
+    let mut warnings = Vec::new();
+    if state.manager.is_running(&name).await {
+        warnings.push(
+            "recording is running; finalize stops it first, so segments written until then \
+             are added"
+                .to_string(),
+        );
+    }
+    let dst_dir = state.finished_dir.join(&name);
+    if fs::metadata(&dst_dir).await.is_ok_and(|md| !md.is_dir()) {
+        warnings.push(format!(
+            "{} exists and is not a directory; finalize would fail",
+            dst_dir.display()
+        ));
+    }
+    for seg in &plan.segments {
+        match normalize_segment_path(&state.pending_dir, seg).await {
+            Ok(path) if fs::metadata(&path).await.is_ok_and(|md| md.len() == 0) => {
+                warnings.push(format!("segment {} is empty", seg));
+            }
+            Ok(_) => {}
+            Err(e) => warnings.push(format!("segment {} cannot be moved: {:#}", seg, e)),
+        }
+    }
+    if Playlist::parse(&playlist).segments.is_empty() {
+        warnings.push("playlist has no segments; finalize would fail".to_string());
+    }
+
+    Ok(FinalizePreview {
+        segments: plan
+            .segments
+            .iter()
+            .map(|seg| PreviewMove {
+                from: seg.clone(),
+                to: plan.vod_name(seg),
+            })
+            .collect(),
+        dropped: plan.dropped,
+        playlist,
+        warnings,
+    })
+}
+
+/// Move a stopped recording from `pending_dir` into its VOD folder and
+/// write the VOD playlist. Only touches files below the two directories.
+/// Segments are moved and listed in the order of the source playlist.
+pub async fn finalize_files(
+    dirs: &FinalizeDirs<'_>,
+    name: &str,
+    opts: FinalizeOpts,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    // 2) read event playlist
+    let plan = FinalizePlan::read(dirs.pending_dir, name, opts).await?;
+    let segments = &plan.segments;
+
+    // 3) prepare destination directory
+    let dst_dir = dirs.finished_dir.join(name);
+    let dst_pl = dst_dir.join(dirs.vod_playlist);
+    if let Ok(md) = fs::metadata(&dst_dir).await
+        && !md.is_dir()
+    {
+        anyhow::bail!(
+            "Cannot finalize '{}': {} exists and is not a directory; move it out of the way first",
+            name,
+            dst_dir.display()
+        );
+    }
+    fs::create_dir_all(&dst_dir)
+        .await
+        .with_context(|| format!("failed to create VOD folder {}", dst_dir.display()))?;
+
+    // 4) move segments without duplication and adjust URIs. The order of
+    // the moves does not matter, the playlist is rewritten from `content`.
+    let concurrency = dirs.settings.finalize_concurrency.max(1);
+    info!(%name, total_segments=segments.len(), concurrency, "moving segments");
+    // with a grace period the live copies stay until `leftovers` are removed
+    let grace = dirs.settings.finalize_grace_secs;
+    let mut leftovers = Vec::new();
+    let mut rollback = Rollback::default();
+    // set on the first failure or a cancel: moves not yet begun are skipped,
+    // the ones in flight are still awaited so every move is known
+    let halt = AtomicBool::new(false);
+    let mut failed = Vec::new();
+    let mut moves = stream::iter(segments.iter().cloned().map(|seg| {
+        let halt = &halt;
+        let dst = dst_dir.join(plan.vod_name(&seg));
+        async move {
+            if halt.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            // moved by an earlier, interrupted finalize: the source is gone
+            if fs::metadata(&dst).await.is_ok() {
+                return Ok(None);
+            }
+            let src = normalize_segment_path(dirs.pending_dir, &seg).await?;
+            move_segment(src, dst, grace > 0).await
+        }
+    }))
+    .buffer_unordered(concurrency);
+    while let Some(result) = moves.next().await {
+        match result {
+            Ok(Some(m)) => {
+                if m.copied {
+                    leftovers.push(m.src.clone());
+                }
+                rollback.moved.push(m);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                halt.store(true, Ordering::Relaxed);
+                failed.push(e);
+            }
+        }
+        if cancel.is_cancelled() {
+            halt.store(true, Ordering::Relaxed);
+        }
+    }
+    if let Some(first) = failed.first() {
+        error!(%name, failed=failed.len(), moved=rollback.moved.len(), "segment moves failed - restoring segments");
+        rollback.undo(&dst_dir).await;
+        anyhow::bail!(
+            "{} of {} segments could not be moved, first error: {:#}",
+            failed.len(),
+            segments.len(),
+            first
+        );
+    }
+    if cancel.is_cancelled() {
+        info!(%name, moved=rollback.moved.len(), "finalize cancelled - restoring segments");
+        rollback.undo(&dst_dir).await;
+        return Err(Cancelled.into());
+    }
+
+    // audio sidecars keep their file names
+    let mut audio_files = Vec::new();
+    for src in list_sidecar_files(dirs.pending_dir, name, AUDIO_EXT).await {
+        let fname = src.file_name().unwrap().to_string_lossy().to_string();
+        let dst = dst_dir.join(&fname);
+        if let Err(e) = rollback.move_file(&src, &dst).await {
+            error!(src=?src, dst=?dst, error=?e, "audio move failed");
+            rollback.undo(&dst_dir).await;
+            anyhow::bail!("Could not move audio file: {}", src.display());
+        }
+        audio_files.push(fname);
+    }
+
+    let mut archive_files = Vec::new();
+    for src in list_sidecar_files(dirs.pending_dir, name, ARCHIVE_EXT).await {
+        let fname = src.file_name().unwrap().to_string_lossy().to_string();
+        let dst = dst_dir.join(&fname);
+        if let Err(e) = rollback.move_file(&src, &dst).await {
+            error!(src=?src, dst=?dst, error=?e, "archive move failed");
+            rollback.undo(&dst_dir).await;
+            anyhow::bail!("Could not move archive file: {}", src.display());
+        }
+        archive_files.push(fname);
+    }
+
+    // until the metadata is saved any failure puts everything back, so a
+    // half finalized recording is never taken for a finished one
+    let src_meta = meta::pending_path(dirs.pending_dir, name);
+    let result = async {
+        // 5) rewrite playlist: EVENT -> VOD, basename URIs, ENDLIST. An
+        // empty or malformed source must not end up as a broken VOD.
+        let vod = plan.vod_playlist()?;
+        let duration = validate_vod_playlist(&vod, &dst_dir)
+            .await
+            .with_context(|| format!("Finalizing '{}' produced an invalid playlist", name))?;
+        rollback.write(&dst_pl, &vod).await?;
+        info!(playlist=?dst_pl, "VOD playlist written");
+
+        let mut rec_meta = meta::load(&src_meta).await;
+        let subtitles =
+            finalize_subtitles(dirs, name, opts, &dst_dir, &mut rollback, &mut leftovers).await?;
+        finalize_audio_tracks(
+            dirs,
+            name,
+            opts,
+            &dst_dir,
+            &mut rec_meta.audio_tracks,
+            &mut rollback,
+            &mut leftovers,
+        )
+        .await?;
+        let iframes =
+            finalize_iframes(dirs, name, opts, &dst_dir, &mut rollback, &mut leftovers).await?;
+        let master = write_master(
+            &dst_dir,
+            &dst_pl,
+            subtitles,
+            &rec_meta.audio_tracks,
+            iframes,
+            &mut rollback,
+        )
+        .await?;
+        if master.is_some() {
+            info!(%name, "master playlist written");
+        }
+
+        // 6) move metadata next to the VOD playlist
+        let dst_meta = meta::finished_path(dirs.finished_dir, name);
+        rec_meta.audio = audio_files;
+        rec_meta.archive = archive_files;
+        rec_meta.master = master;
+        rec_meta.iframes = iframes.then(|| VOD_IFRAME_PLAYLIST.to_string());
+        rec_meta.duration_secs = Some(duration);
+        rollback.written.push(dst_meta.clone());
+        meta::save(&dst_meta, &rec_meta).await
+    }
+    .await;
+    if let Err(e) = result {
+        error!(%name, error=?e, "finalize failed - rolling back");
+        rollback.undo(&dst_dir).await;
+        return Err(e);
+    }
+    fs::remove_file(&src_meta).await.ok();
+
+    // segments outside the trim window are not part of the VOD
+    if !plan.dropped.is_empty() {
+        info!(%name, trimmed=plan.dropped.len(), "removing trimmed segments");
+    }
+    for seg in &plan.dropped {
+        if let Ok(src) = normalize_segment_path(dirs.pending_dir, seg).await {
+            leftovers.push(src);
+        }
+    }
+
+    // 7) remove pending playlist to save space
+    // emptied directories: segment_subdir, then the archive
+    let mut empty_dirs = vec![segment_dir(dirs.pending_dir, name)];
+    if plan.archived {
+        // segments ffmpeg has not deleted yet were archived as links or copies
+        for seg in segments.iter().chain(&plan.dropped) {
+            for dir in [
+                dirs.pending_dir.to_path_buf(),
+                segment_dir(dirs.pending_dir, name),
+            ] {
+                let live = dir.join(segment_basename(seg));
+                if fs::metadata(&live).await.is_ok() {
+                    leftovers.push(live);
+                }
+            }
+        }
+        leftovers.push(archive::playlist_path(dirs.pending_dir, name));
+        empty_dirs.push(archive::dir_path(dirs.pending_dir, name));
+    }
+    leftovers.push(dirs.pending_dir.join(format!("{}.m3u8", name)));
+
+    let cleanup = Cleanup {
+        finalized: unix_now(),
+        grace_secs: grace,
+        files: leftovers,
+        dirs: empty_dirs,
+    };
+    let saved = grace > 0
+        && match save_cleanup(dirs.pending_dir, name, &cleanup).await {
+            Ok(()) => true,
+            Err(e) => {
+                error!(%name, error=?e, "failed to save the grace cleanup - removing live files now");
+                false
+            }
+        };
+    if saved {
+        info!(%name, grace_secs = grace, "keeping live files during the grace period");
+    } else {
+        remove_leftovers(dirs.settings, &cleanup.files, &cleanup.dirs).await;
+    }
+
+    info!(%name, "recording finalized");
+    Ok(())
+}
+
+/// Move the WebVTT segments of all runs into the VOD folder and merge their
+/// lists into one subtitle playlist. Returns whether it was written.
+async fn finalize_subtitles(
+    dirs: &FinalizeDirs<'_>,
+    name: &str,
+    trim: FinalizeOpts,
+    dst_dir: &Path,
+    rollback: &mut Rollback,
+    leftovers: &mut Vec<PathBuf>,
+) -> Result<bool> {
+    let lists = list_sidecar_files(dirs.pending_dir, name, SUBTITLE_LIST_EXT).await;
+    if lists.is_empty() {
+        return Ok(false);
+    }
+
+    // one list per ffmpeg run; later runs continue after a discontinuity
+    let mut merged = String::from("#EXTM3U\n");
+    for (i, list) in lists.iter().enumerate() {
+        let content = fs::read_to_string(list).await?;
+        if i > 0 {
+            merged.push_str("#EXT-X-DISCONTINUITY\n");
+        }
+        for line in content.lines() {
+            if !line.starts_with("#EXTM3U") && !is_playlist_tag(line) {
+                merged.push_str(line);
+                merged.push('\n');
+            }
+        }
+    }
+
+    let window = if trim.is_set() {
+        match trim_window(&merged, trim) {
+            Ok(w) => Some(w),
+            Err(e) => {
+                warn!(%name, error=?e, "no subtitles inside the trim window");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let drop_all = trim.is_set() && window.is_none();
+    for seg in parse_segments(&merged) {
+        let src = normalize_segment_path(dirs.pending_dir, &seg.uri).await?;
+        let base = segment_basename(&seg.uri);
+        if drop_all || window.as_ref().is_some_and(|w| !w.keep.contains(&base)) {
+            leftovers.push(src);
+            continue;
+        }
+        let dst = dst_dir.join(&base);
+        if let Err(e) = rollback.move_file(&src, &dst).await {
+            error!(src=?src, dst=?dst, error=?e, "subtitle move failed");
+            anyhow::bail!("Could not move subtitle segment: {}", src.display());
+        }
+    }
+    leftovers.extend(lists);
+    if drop_all {
+        return Ok(false);
+    }
+
+    let vod = rewrite_playlist_to_vod(&merged, window.as_ref(), None)?;
+    rollback
+        .write(&dst_dir.join(VOD_SUBTITLE_PLAYLIST), &vod)
+        .await?;
+    info!(%name, "subtitle playlist written");
+    Ok(true)
+}
+
+/// Move the segments of every audio track after the first into the VOD
+/// folder and write a media playlist per track into `tracks`.
+async fn finalize_audio_tracks(
+    dirs: &FinalizeDirs<'_>,
+    name: &str,
+    trim: FinalizeOpts,
+    dst_dir: &Path,
+    tracks: &mut [AudioTrack],
+    rollback: &mut Rollback,
+    leftovers: &mut Vec<PathBuf>,
+) -> Result<()> {
+    for (i, track) in tracks.iter_mut().enumerate().skip(1) {
+        let src_pl = audio_track_playlist(dirs.pending_dir, name, i);
+        let Ok(content) = fs::read_to_string(&src_pl).await else {
+            warn!(%name, track = i, "audio track playlist missing");
+            continue;
+        };
+        // tracks are segmented independently, so trim them by time
+        let window = if trim.is_set() {
+            Some(trim_window(&content, trim)?)
+        } else {
+            None
+        };
+        for seg in extract_segment_list(&content) {
+            let src = normalize_segment_path(dirs.pending_dir, &seg).await?;
+            let base = segment_basename(&seg);
+            if window.as_ref().is_some_and(|w| !w.keep.contains(&base)) {
+                leftovers.push(src);
+            } else if let Err(e) = rollback.move_file(&src, &dst_dir.join(&base)).await {
+                error!(src=?src, error=?e, "audio track segment move failed");
+                anyhow::bail!("Could not move segment: {}", src.display());
+            }
+        }
+        let file = format!("audio_{}.m3u8", i);
+        let vod = rewrite_playlist_to_vod(&content, window.as_ref(), None)?;
+        rollback.write(&dst_dir.join(&file), &vod).await?;
+        leftovers.push(src_pl);
+        track.playlist = Some(file);
+    }
+    Ok(())
+}
+
+/// Move the I-frame segments into the VOD folder and write
+/// `iframes.m3u8`. Returns `false` if the recording has none.
+async fn finalize_iframes(
+    dirs: &FinalizeDirs<'_>,
+    name: &str,
+    trim: FinalizeOpts,
+    dst_dir: &Path,
+    rollback: &mut Rollback,
+    leftovers: &mut Vec<PathBuf>,
+) -> Result<bool> {
+    let src_pl = iframe_playlist(dirs.pending_dir, name);
+    let Ok(content) = fs::read_to_string(&src_pl).await else {
+        return Ok(false);
+    };
+    let window = if trim.is_set() {
+        Some(trim_window(&content, trim)?)
+    } else {
+        None
+    };
+    let mut files = HashSet::new();
+    for seg in parse_segments(&content) {
+        let base = segment_basename(&seg.uri);
+        // several I-frames (byte ranges) share a segment file
+        if !files.insert(base.clone()) {
+            continue;
+        }
+        let src = normalize_segment_path(dirs.pending_dir, &seg.uri).await?;
+        if window.as_ref().is_some_and(|w| !w.keep.contains(&base)) {
+            leftovers.push(src);
+        } else if let Err(e) = rollback.move_file(&src, &dst_dir.join(&base)).await {
+            error!(src=?src, error=?e, "I-frame segment move failed");
+            anyhow::bail!("Could not move segment: {}", src.display());
+        }
+    }
+    let vod = rewrite_playlist_to_vod(&content, window.as_ref(), None)?;
+    rollback
+        .write(&dst_dir.join(VOD_IFRAME_PLAYLIST), &vod)
+        .await?;
+    leftovers.push(src_pl);
+    info!(%name, "I-frame playlist written");
+    Ok(true)
+}
+
+/// Write `master.m3u8` referencing the subtitle playlist, the audio tracks
+/// and the I-frame playlist. Returns its name, or `None` if the VOD needs
+/// no master.
+async fn write_master(
+    dst_dir: &Path,
+    dst_pl: &Path,
+    subtitles: bool,
+    audio_tracks: &[AudioTrack],
+    iframes: bool,
+    rollback: &mut Rollback,
+) -> Result<Option<String>> {
+    let alternate_audio = audio_tracks.iter().skip(1).any(|t| t.playlist.is_some());
+    if !subtitles && !alternate_audio && !iframes {
+        return Ok(None);
+    }
+
+    let mut master = String::from("#EXTM3U\n");
+    let mut groups = String::new();
+    if alternate_audio {
+        for (i, track) in audio_tracks.iter().enumerate() {
+            if i > 0 && track.playlist.is_none() {
+                continue;
+            }
+            let label = track
+                .language
+                .clone()
+                .unwrap_or_else(|| format!("Track {}", i + 1));
+            master.push_str(&format!(
+                "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"{}\",DEFAULT={},AUTOSELECT=YES",
+                label,
+                if i == 0 { "YES" } else { "NO" }
+            ));
+            if let Some(lang) = &track.language {
+                master.push_str(&format!(",LANGUAGE=\"{}\"", lang));
+            }
+            // the first track is muxed into the main playlist
+            if let Some(pl) = &track.playlist {
+                master.push_str(&format!(",URI=\"{}\"", pl));
+            }
+            master.push('\n');
+        }
+        groups.push_str(",AUDIO=\"aud\"");
+    }
+    if subtitles {
+        master.push_str(&format!(
+            "#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"subs\",NAME=\"Subtitles\",DEFAULT=NO,AUTOSELECT=YES,URI=\"{}\"\n",
+            VOD_SUBTITLE_PLAYLIST
+        ));
+        groups.push_str(",SUBTITLES=\"subs\"");
+    }
+    master.push_str(&format!(
+        "#EXT-X-STREAM-INF:BANDWIDTH={}{}\n{}\n",
+        estimate_bandwidth(dst_pl).await,
+        groups,
+        dst_pl.file_name().unwrap().to_string_lossy(),
+    ));
+    if iframes {
+        master.push_str(&format!(
+            "#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH={},URI=\"{}\"\n",
+            estimate_bandwidth(&dst_dir.join(VOD_IFRAME_PLAYLIST)).await,
+            VOD_IFRAME_PLAYLIST
+        ));
+    }
+    rollback
+        .write(&dst_dir.join(VOD_MASTER_PLAYLIST), &master)
+        .await?;
+    Ok(Some(VOD_MASTER_PLAYLIST.to_string()))
+}
+
+/// Average bits per second of a VOD playlist, from its segment sizes.
+async fn estimate_bandwidth(playlist: &Path) -> u64 {
+    let Ok(content) = fs::read_to_string(playlist).await else {
+        return 0;
+    };
+    let dir = playlist.parent().unwrap_or(Path::new("."));
+    let mut bytes = 0;
+    let mut secs = 0.0;
+    for seg in parse_segments(&content) {
+        // I-frame entries only cover a byte range of their file
+        if let Some(len) = byterange_length(&seg) {
+            bytes += len;
+            secs += seg.duration;
+        } else if let Ok(md) = fs::metadata(dir.join(&seg.uri)).await {
+            bytes += md.len();
+            secs += seg.duration;
+        }
+    }
+    if secs > 0.0 {
+        (bytes as f64 * 8.0 / secs) as u64
+    } else {
+        0
+    }
+}
+
+/// Length from a segment's `#EXT-X-BYTERANGE:<n>[@<o>]` tag.
+fn byterange_length(seg: &Segment) -> Option<u64> {
+    seg.tags.iter().find_map(|t| {
+        t.strip_prefix("#EXT-X-BYTERANGE:")?
+            .split('@')
+            .next()?
+            .trim()
+            .parse()
+            .ok()
+    })
+}
+
+/// Identifies a playlist entry for de-duplication: the file name, plus the
+/// byte range for entries that share a file (I-frame playlists).
+fn segment_key(seg: &Segment) -> String {
+    let range = seg.tags.iter().find(|t| t.starts_with("#EXT-X-BYTERANGE:"));
+    match range {
+        Some(r) => format!("{} {}", segment_basename(&seg.uri), r),
+        None => segment_basename(&seg.uri),
+    }
+}
+
+/// A file finalize has put into the VOD folder, see `undo_moves`.
+struct Moved {
+    src: PathBuf,
+    dst: PathBuf,
+    /// `src` is still in place (a copy for the grace period)
+    copied: bool,
+}
+
+impl Moved {
+    fn renamed(src: PathBuf, dst: PathBuf) -> Self {
+        Self {
+            src,
+            dst,
+            copied: false,
+        }
+    }
+}
+
+/// Move (or with `copy` hard-link/copy) one segment into the VOD folder.
+/// `None` if it is already there.
+async fn move_segment(src: PathBuf, dst: PathBuf, copy: bool) -> Result<Option<Moved>> {
+    if fs::metadata(&dst).await.is_ok() {
+        debug!(dst=?dst, "segment already moved, skipping");
+        return Ok(None);
+    }
+    if copy {
+        debug!(src=?src, dst=?dst, "copying segment");
+        if let Err(e) = link_or_copy(&src, &dst).await {
+            error!(src=?src, dst=?dst, error=?e, "segment copy failed");
+            anyhow::bail!("Could not copy segment: {}", src.display());
+        }
+        return Ok(Some(Moved {
+            src,
+            dst,
+            copied: true,
+        }));
+    }
+    debug!(src=?src, dst=?dst, "moving segment");
+    match move_file(&src, &dst).await {
+        Ok(()) => Ok(Some(Moved::renamed(src, dst))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && fs::metadata(&dst).await.is_ok() => {
+            debug!(dst=?dst, "segment already moved, skipping");
+            Ok(None)
+        }
+        Err(e) => {
+            error!(src=?src, dst=?dst, error=?e, "segment move failed");
+            anyhow::bail!("Could not move segment: {}", src.display());
+        }
+    }
+}
+
+/// `rename`, or on a different file system a link (or copy) and removal.
+async fn move_file(src: &Path, dst: &Path) -> std::io::Result<()> {
+    match fs::rename(src, dst).await {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            link_or_copy(src, dst).await?;
+            fs::remove_file(src).await
+        }
+        result => result,
+    }
+}
+
+async fn link_or_copy(src: &Path, dst: &Path) -> std::io::Result<()> {
+    if fs::hard_link(src, dst).await.is_err() {
+        fs::copy(src, dst).await?;
+    }
+    Ok(())
+}
+
+/// Delete a recording artifact, refusing files whose extension is not in
+/// the cleanup allowlist (e.g. notes a user dropped into the folder).
+pub async fn remove_artifact(settings: &Settings, path: &Path) -> Result<()> {
+    if !settings.may_remove(path) {
+        warn!(file=?path, "not removing file outside the cleanup allowlist");
+        anyhow::bail!("refusing to remove {}", path.display());
+    }
+    fs::remove_file(path).await?;
+    Ok(())
+}
+
+/// Delete everything a recording left in the pending directory: its
+/// playlists with the segments they list, metadata and sidecar files.
+pub async fn discard_pending(state: &AppState, name: &str) -> Result<()> {
+    let settings = state.settings.load();
+    let prefix = format!("{}.", name);
+    let mut files = Vec::new();
+    let mut rd = fs::read_dir(&state.pending_dir).await?;
+    while let Some(entry) = rd.next_entry().await? {
+        if let Some(f) = entry.file_name().to_str()
+            && f.starts_with(&prefix)
+        {
+            files.push(entry.path());
+        }
     }
 
-    Ok(out)
+    for file in &files {
+        if !file.to_string_lossy().ends_with(".m3u8") {
+            continue;
+        }
+        let content = fs::read_to_string(file).await.unwrap_or_default();
+        for seg in parse_segments(&content) {
+            if let Ok(path) = normalize_segment_path(&state.pending_dir, &seg.uri).await {
+                remove_artifact(&settings, &path).await.ok();
+            }
+        }
+    }
+    for file in &files {
+        let f = file.file_name().unwrap_or_default().to_string_lossy();
+        // our own outputs, even if the cleanup allowlist does not cover them
+        if f == format!("{}.meta.json", name)
+            || is_sidecar_file(name, &f, AUDIO_EXT)
+            || is_sidecar_file(name, &f, ARCHIVE_EXT)
+        {
+            fs::remove_file(file).await.ok();
+        } else {
+            remove_artifact(&settings, file).await.ok();
+        }
+    }
+    for dir in [
+        segment_dir(&state.pending_dir, name),
+        archive::dir_path(&state.pending_dir, name),
+    ] {
+        fs::remove_dir(dir).await.ok();
+    }
+    info!(%name, "pending files removed");
+    Ok(())
+}
+
+/// Remove the pending files of a finalized recording, then those of `dirs`
+/// that are empty.
+async fn remove_leftovers(settings: &Settings, files: &[PathBuf], dirs: &[PathBuf]) {
+    for file in files {
+        if fs::metadata(file).await.is_err() {
+            continue;
+        }
+        if let Err(e) = remove_artifact(settings, file).await {
+            error!(file=?file, error=?e, "failed to remove pending file");
+        }
+    }
+    for dir in dirs {
+        fs::remove_dir(dir).await.ok();
+    }
 }
 
-fn normalize_segment_path(pending_dir: &Path, seg: &str) -> Result<PathBuf> {
-    let p = Path::new(seg);
+/// Live files finalize keeps for `finalize_grace_secs`. Saved next to the
+/// pending playlist so a restart during the grace period still removes them.
+#[derive(Serialize, Deserialize)]
+struct Cleanup {
+    /// Seconds since the epoch
+    finalized: u64,
+    grace_secs: u64,
+    files: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+}
+
+fn cleanup_path(pending_dir: &Path, name: &str) -> PathBuf {
+    pending_dir.join(format!("{}.cleanup.json", name))
+}
+
+async fn save_cleanup(pending_dir: &Path, name: &str, cleanup: &Cleanup) -> Result<()> {
+    fs::write(
+        cleanup_path(pending_dir, name),
+        serde_json::to_vec(cleanup)?,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Remove the live files of `name` once its grace period is over, if
+/// finalize left a cleanup for them.
+pub fn spawn_cleanup(state: AppState, name: String) {
+    tokio::spawn(async move { run_cleanup(&state, &name).await });
+}
+
+/// Pick up the grace cleanups of a previous run.
+pub async fn resume_cleanups(state: &AppState) -> Result<()> {
+    let mut entries = fs::read_dir(&state.pending_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if let Some(name) = entry
+            .file_name()
+            .to_str()
+            .and_then(|f| f.strip_suffix(".cleanup.json"))
+        {
+            spawn_cleanup(state.clone(), name.to_string());
+        }
+    }
+    Ok(())
+}
+
+async fn run_cleanup(state: &AppState, name: &str) {
+    let path = cleanup_path(&state.pending_dir, name);
+    let Ok(bytes) = fs::read(&path).await else {
+        return;
+    };
+    let cleanup: Cleanup = match serde_json::from_slice(&bytes) {
+        Ok(c) => c,
+        Err(e) => {
+            error!(file=?path, error=?e, "unreadable grace cleanup - leaving the files");
+            return;
+        }
+    };
+    let due = cleanup.finalized + cleanup.grace_secs;
+    sleep(Duration::from_secs(due.saturating_sub(unix_now()))).await;
+
+    // a recording started again under the same name may still use some of
+    // the files (e.g. resumed onto the old playlist)
+    let mut files = cleanup.files;
+    if state.manager.is_running(name).await {
+        let used = files_in_use(&state.pending_dir, name).await;
+        let mut kept = Vec::new();
+        for file in files {
+            if used.contains(&file) || modified_after(&file, cleanup.finalized).await {
+                continue;
+            }
+            kept.push(file);
+        }
+        files = kept;
+    }
+    remove_leftovers(&state.settings.load(), &files, &cleanup.dirs).await;
+    fs::remove_file(&path).await.ok();
+    debug!(%name, "grace period over - live files removed");
+}
+
+/// The live playlist of `name` and the segments it lists.
+async fn files_in_use(pending_dir: &Path, name: &str) -> HashSet<PathBuf> {
+    let playlist = pending_dir.join(format!("{}.m3u8", name));
+    let mut used = HashSet::new();
+    if let Ok(content) = fs::read_to_string(&playlist).await {
+        for seg in parse_segments(&content) {
+            if let Ok(path) = normalize_segment_path(pending_dir, &seg.uri).await {
+                used.insert(path);
+            }
+        }
+    }
+    used.insert(playlist);
+    used
+}
+
+async fn modified_after(path: &Path, secs: u64) -> bool {
+    fs::metadata(path)
+        .await
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .is_some_and(|t| t.as_secs() >= secs)
+}
+
+/// What finalize has put into the VOD folder so far, to be undone if a
+/// later step fails.
+#[derive(Default)]
+struct Rollback {
+    moved: Vec<Moved>,
+    /// Playlists and metadata written
+    written: Vec<PathBuf>,
+}
+
+impl Rollback {
+    async fn move_file(&mut self, src: &Path, dst: &Path) -> std::io::Result<()> {
+        move_file(src, dst).await?;
+        self.moved
+            .push(Moved::renamed(src.to_path_buf(), dst.to_path_buf()));
+        Ok(())
+    }
+
+    async fn write(&mut self, path: &Path, content: &str) -> Result<()> {
+        self.written.push(path.to_path_buf());
+        fs::write(path, content).await?;
+        Ok(())
+    }
+
+    async fn undo(&self, dst_dir: &Path) {
+        for path in self.written.iter().rev() {
+            fs::remove_file(path).await.ok();
+        }
+        undo_moves(&self.moved, dst_dir).await;
+    }
+}
+
+/// Put already moved files back into pending (copies are just deleted) and
+/// drop the partial destination directory if nothing else is left in it.
+async fn undo_moves(moved: &[Moved], dst_dir: &Path) {
+    for m in moved.iter().rev() {
+        let result = if m.copied {
+            fs::remove_file(&m.dst).await
+        } else {
+            move_file(&m.dst, &m.src).await
+        };
+        if let Err(e) = result {
+            error!(src=?m.dst, dst=?m.src, error=?e, "failed to restore segment");
+        }
+    }
+    fs::remove_dir(dst_dir).await.ok();
+}
+
+/// Segments are stored flat in the VOD folder, so two URIs with the same
+/// file name refer to the same file there.
+fn segment_basename(uri: &str) -> String {
+    Path::new(uri_path(uri))
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| uri.to_string())
+}
+
+/// Segment URIs in playlist order. Never order segments by file name: the
+/// strftime part follows the wall clock, which can jump back after a clock
+/// adjustment, and the counter starts over with every ffmpeg run.
+pub fn extract_segment_list(playlist: &str) -> Vec<String> {
+    // Repeated segments (e.g. from a hand-edited playlist) are only listed
+    // once. Subtitle segments are handled separately.
+    let mut seen = HashSet::new();
+    parse_segments(playlist)
+        .into_iter()
+        .filter(|s| !is_subtitle_uri(&s.uri))
+        .filter(|s| seen.insert(segment_basename(&s.uri)))
+        .map(|s| s.uri)
+        .collect()
+}
+
+/// Sequential VOD file names for `segments` (in playlist order), keyed by
+/// their current file name: `segment_00001.ts`, `segment_00002.ts`, ...
+fn renumbered_names(segments: &[String]) -> HashMap<String, String> {
+    segments
+        .iter()
+        .enumerate()
+        .map(|(i, seg)| {
+            let base = segment_basename(seg);
+            let renamed = match Path::new(&base).extension() {
+                Some(ext) => format!("segment_{:05}.{}", i + 1, ext.to_string_lossy()),
+                None => format!("segment_{:05}", i + 1),
+            };
+            (base, renamed)
+        })
+        .collect()
+}
+
+fn trim_window(playlist: &str, trim: FinalizeOpts) -> Result<TrimWindow> {
+    let start = trim.trim_start.unwrap_or(0.0);
+    let end = trim.trim_end.unwrap_or(f64::INFINITY);
+    if !(start >= 0.0 && end > start) {
+        anyhow::bail!("invalid trim window {}..{}", start, end);
+    }
+
+    let mut seen = HashSet::new();
+    let segments: Vec<Segment> = parse_segments(playlist)
+        .into_iter()
+        .filter(|s| seen.insert(segment_key(s)))
+        .collect();
+
+    // keep every segment overlapping the window
+    let mut keep = HashSet::new();
+    let mut first = None;
+    let mut t = 0.0;
+    for (i, seg) in segments.iter().enumerate() {
+        if t + seg.duration > start && t < end {
+            keep.insert(segment_basename(&seg.uri));
+            first.get_or_insert(i);
+        }
+        t += seg.duration;
+    }
+    let Some(first) = first else {
+        anyhow::bail!("trim window {}..{} contains no segments", start, end);
+    };
+    Ok(TrimWindow {
+        keep,
+        first_pdt: program_date_time_at(&segments, first),
+    })
+}
+
+fn rewrite_playlist_to_vod(
+    original: &str,
+    trim: Option<&TrimWindow>,
+    renames: Option<&HashMap<String, String>>,
+) -> Result<String> {
+    // EVENT -> VOD with ENDLIST; segment URIs become basenames (or their
+    // `renames`). Duplicate
+    // and trimmed segments are dropped together with their tags, except for
+    // ad markers still needed around the trim window.
+    let mut playlist = Playlist::parse(original);
+    playlist.playlist_type = Some("VOD".to_string());
+    playlist.endlist = true;
+
+    let mut seen = HashSet::new();
+    playlist.segments.retain(|seg| {
+        let new = seen.insert(segment_key(seg));
+        if !new {
+            debug!(segment=%segment_basename(&seg.uri), "dropping duplicate segment");
+        }
+        new
+    });
+
+    if let Some(w) = trim {
+        // the window is contiguous
+        let kept = |seg: &Segment| w.keep.contains(&segment_basename(&seg.uri));
+        let first = playlist.segments.iter().position(kept);
+        let last = playlist.segments.iter().rposition(kept);
+        if let (Some(first), Some(last)) = (first, last) {
+            let carried = carried_markers(&playlist.segments, first);
+            let closing = closing_marker(&playlist.segments, last, &playlist.trailer);
+            let seg = &mut playlist.segments[first];
+            seg.tags.splice(0..0, carried);
+            // the original PDT may sit on a trimmed segment
+            if seg.program_date_time.is_none() {
+                seg.program_date_time = w.first_pdt.clone();
+            }
+            if last + 1 < playlist.segments.len() {
+                playlist.trailer.retain(|t| !is_ad_marker(t));
+                playlist.trailer.extend(closing);
+            }
+            playlist.segments.truncate(last + 1);
+            playlist.segments.drain(..first);
+        } else {
+            playlist.segments.clear();
+        }
+    }
+    for seg in &mut playlist.segments {
+        let base = segment_basename(&seg.uri);
+        seg.uri = renames.and_then(|r| r.get(&base).cloned()).unwrap_or(base);
+    }
+    // the source's value may be a guess (the archive starts from the first
+    // segments) or cover segments trimmed away
+    if let Some(longest) = playlist
+        .segments
+        .iter()
+        .map(|s| s.duration.round() as u64)
+        .max()
+    {
+        playlist.target_duration = Some(longest.max(1));
+    }
+
+    Ok(playlist.to_string())
+}
+
+/// Check a written VOD playlist: header, type, target duration, at least
+/// one segment that exists in `dir`, and the end marker. Returns the total
+/// duration in seconds.
+async fn validate_vod_playlist(content: &str, dir: &Path) -> Result<f64> {
+    if content.lines().next().map(str::trim) != Some("#EXTM3U") {
+        anyhow::bail!("missing #EXTM3U header");
+    }
+    if content.lines().map(str::trim).rfind(|l| !l.is_empty()) != Some("#EXT-X-ENDLIST") {
+        anyhow::bail!("does not end with #EXT-X-ENDLIST");
+    }
+    let playlist = Playlist::parse(content);
+    if playlist.playlist_type.as_deref() != Some("VOD") {
+        anyhow::bail!("playlist type is not VOD");
+    }
+    if playlist.target_duration.is_none() {
+        anyhow::bail!("missing #EXT-X-TARGETDURATION");
+    }
+    if playlist.segments.is_empty() {
+        anyhow::bail!("playlist has no segments");
+    }
+    for seg in &playlist.segments {
+        if fs::metadata(dir.join(uri_path(&seg.uri))).await.is_err() {
+            anyhow::bail!("segment {} is missing", seg.uri);
+        }
+    }
+    Ok(playlist.segments.iter().map(|s| s.duration).sum())
+}
+
+pub async fn normalize_segment_path(pending_dir: &Path, seg: &str) -> Result<PathBuf> {
+    let p = Path::new(uri_path(seg));
     let joined = if p.is_absolute() {
         p.to_path_buf()
     } else {
         pending_dir.join(p)
     };
 
-    let base = std::fs::canonicalize(pending_dir).with_context(|| {
+    let base = fs::canonicalize(pending_dir).await.with_context(|| {
         format!(
             "failed to canonicalize pending dir {}",
             pending_dir.display()
         )
     })?;
-    let canon = std::fs::canonicalize(&joined)
+    let canon = fs::canonicalize(&joined)
+        .await
         .with_context(|| format!("failed to canonicalize segment path {}", joined.display()))?;
 
     if canon.starts_with(&base) {
@@ -314,3 +2215,422 @@ pub fn _probe_input(url: &str) -> Result<()> {
     let _ = ictx.streams();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        testutil::{self, TempDir},
+    };
+
+    /// A pending EVENT playlist for `name` with 6 s segments that exist.
+    fn write_pending(pending_dir: &Path, name: &str, segments: &[&str]) {
+        let mut playlist =
+            "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-PLAYLIST-TYPE:EVENT\n".to_string();
+        for seg in segments {
+            std::fs::write(pending_dir.join(seg), b"ts").unwrap();
+            playlist.push_str(&format!("#EXTINF:6.000000,\n{}\n", seg));
+        }
+        std::fs::write(pending_dir.join(format!("{}.m3u8", name)), playlist).unwrap();
+    }
+
+    fn trim(start: Option<f64>, end: Option<f64>) -> FinalizeOpts {
+        FinalizeOpts {
+            trim_start: start,
+            trim_end: end,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn trim_after_the_only_date_time_recomputes_it() {
+        let playlist = "#EXTM3U
+#EXT-X-TARGETDURATION:6
+#EXT-X-PROGRAM-DATE-TIME:2024-01-31T12:00:00.000+0100
+#EXTINF:6.000000,
+seg_000.ts
+#EXTINF:6.000000,
+seg_001.ts
+#EXTINF:6.000000,
+seg_002.ts
+#EXTINF:6.000000,
+seg_003.ts
+";
+        let window = trim_window(playlist, trim(Some(13.0), None)).unwrap();
+        assert_eq!(
+            window.first_pdt.as_deref(),
+            Some("2024-01-31T12:00:12.000+0100")
+        );
+
+        let vod = Playlist::parse(&rewrite_playlist_to_vod(playlist, Some(&window), None).unwrap());
+        let uris: Vec<_> = vod.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(uris, ["seg_002.ts", "seg_003.ts"]);
+        assert_eq!(
+            vod.segments[0].program_date_time.as_deref(),
+            Some("2024-01-31T12:00:12.000+0100")
+        );
+    }
+
+    #[test]
+    fn trim_inside_an_ad_break_reopens_and_closes_it() {
+        let playlist = "#EXTM3U
+#EXT-X-TARGETDURATION:6
+#EXTINF:6.000000,
+seg_000.ts
+#EXT-X-DATERANGE:ID=\"ad1\",START-DATE=\"2024-01-31T12:00:06.000+0100\"
+#EXT-X-CUE-OUT:DURATION=18
+#EXTINF:6.000000,
+seg_001.ts
+#EXT-X-CUE-OUT-CONT:ElapsedTime=6,Duration=18
+#EXTINF:6.000000,
+seg_002.ts
+#EXT-X-CUE-OUT-CONT:ElapsedTime=12,Duration=18
+#EXTINF:6.000000,
+seg_003.ts
+#EXT-X-CUE-IN
+#EXTINF:6.000000,
+seg_004.ts
+";
+        let window = trim_window(playlist, trim(Some(13.0), Some(23.0))).unwrap();
+        let vod = Playlist::parse(&rewrite_playlist_to_vod(playlist, Some(&window), None).unwrap());
+
+        let uris: Vec<_> = vod.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(uris, ["seg_002.ts", "seg_003.ts"]);
+        assert_eq!(
+            vod.segments[0].tags,
+            [
+                "#EXT-X-DATERANGE:ID=\"ad1\",START-DATE=\"2024-01-31T12:00:06.000+0100\"",
+                "#EXT-X-CUE-OUT:DURATION=18",
+                "#EXT-X-CUE-OUT-CONT:ElapsedTime=6,Duration=18",
+            ]
+        );
+        assert_eq!(vod.trailer, ["#EXT-X-CUE-IN"]);
+    }
+
+    #[test]
+    fn repeated_segments_are_listed_once() {
+        let playlist = "#EXTM3U
+#EXT-X-TARGETDURATION:6
+#EXTINF:6.000000,
+seg_000.ts
+#EXTINF:6.000000,
+seg_001.ts
+#EXTINF:6.000000,
+seg_001.ts
+#EXTINF:6.000000,
+seg_002.ts
+";
+        assert_eq!(
+            extract_segment_list(playlist),
+            ["seg_000.ts", "seg_001.ts", "seg_002.ts"]
+        );
+
+        let vod = Playlist::parse(&rewrite_playlist_to_vod(playlist, None, None).unwrap());
+        let uris: Vec<_> = vod.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(uris, ["seg_000.ts", "seg_001.ts", "seg_002.ts"]);
+        assert_eq!(vod.segments.iter().map(|s| s.duration).sum::<f64>(), 18.0);
+    }
+
+    #[test]
+    fn segments_keep_playlist_order_not_name_order() {
+        // the clock was set back during the first run, then a resumed run
+        // started its counter over
+        let playlist = "#EXTM3U
+#EXT-X-TARGETDURATION:6
+#EXTINF:6.000000,
+cam1_seg_2024-01-31_12-00-00_000.ts
+#EXTINF:6.000000,
+cam1_seg_2024-01-31_11-59-02_001.ts
+#EXT-X-DISCONTINUITY
+#EXTINF:6.000000,
+cam1_seg_2024-01-31_11-59-30_000.ts
+";
+        let segments = extract_segment_list(playlist);
+        assert_eq!(
+            segments,
+            [
+                "cam1_seg_2024-01-31_12-00-00_000.ts",
+                "cam1_seg_2024-01-31_11-59-02_001.ts",
+                "cam1_seg_2024-01-31_11-59-30_000.ts",
+            ]
+        );
+
+        let renames = renumbered_names(&segments);
+        let vod =
+            Playlist::parse(&rewrite_playlist_to_vod(playlist, None, Some(&renames)).unwrap());
+        let uris: Vec<_> = vod.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            ["segment_00001.ts", "segment_00002.ts", "segment_00003.ts"]
+        );
+        assert_eq!(
+            renames["cam1_seg_2024-01-31_11-59-30_000.ts"],
+            "segment_00003.ts"
+        );
+    }
+
+    #[tokio::test]
+    async fn segment_uris_with_a_query_resolve_to_the_file() {
+        let dir = TempDir::new();
+        std::fs::write(dir.path().join("seg_000.ts"), b"ts").unwrap();
+
+        let path = normalize_segment_path(dir.path(), "seg_000.ts?token=abc")
+            .await
+            .unwrap();
+        assert_eq!(path, dir.path().canonicalize().unwrap().join("seg_000.ts"));
+
+        let playlist = "#EXTM3U
+#EXT-X-TARGETDURATION:6
+#EXTINF:6.000000,
+seg_000.ts?token=abc
+";
+        let vod = Playlist::parse(&rewrite_playlist_to_vod(playlist, None, None).unwrap());
+        assert_eq!(vod.segments[0].uri, "seg_000.ts");
+    }
+
+    #[tokio::test]
+    async fn finalize_files_moves_a_recording_into_its_vod_folder() {
+        let dir = TempDir::new();
+        let pending_dir = dir.path().join("pending");
+        let finished_dir = dir.path().join("finished");
+        std::fs::create_dir_all(&pending_dir).unwrap();
+        std::fs::create_dir_all(&finished_dir).unwrap();
+        write_pending(&pending_dir, "cam1", &["cam1_000.ts", "cam1_001.ts"]);
+
+        let dirs = FinalizeDirs {
+            pending_dir: &pending_dir,
+            finished_dir: &finished_dir,
+            vod_playlist: "index.m3u8",
+            settings: &Settings::default(),
+        };
+        finalize_files(
+            &dirs,
+            "cam1",
+            FinalizeOpts::default(),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        let vod_dir = finished_dir.join("cam1");
+        let vod = Playlist::parse(&std::fs::read_to_string(vod_dir.join("index.m3u8")).unwrap());
+        assert_eq!(vod.playlist_type.as_deref(), Some("VOD"));
+        assert!(vod.endlist);
+        let uris: Vec<_> = vod.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(uris, ["cam1_000.ts", "cam1_001.ts"]);
+        for seg in uris {
+            assert!(vod_dir.join(seg).is_file());
+            assert!(!pending_dir.join(seg).exists());
+        }
+        assert!(!pending_dir.join("cam1.m3u8").exists());
+    }
+
+    #[tokio::test]
+    async fn finalize_refuses_a_file_in_place_of_the_vod_folder() {
+        let dir = TempDir::new();
+        let pending_dir = dir.path().join("pending");
+        let finished_dir = dir.path().join("finished");
+        std::fs::create_dir_all(&pending_dir).unwrap();
+        std::fs::create_dir_all(&finished_dir).unwrap();
+        write_pending(&pending_dir, "cam1", &["cam1_000.ts"]);
+        std::fs::write(finished_dir.join("cam1"), b"not a folder").unwrap();
+
+        let dirs = FinalizeDirs {
+            pending_dir: &pending_dir,
+            finished_dir: &finished_dir,
+            vod_playlist: "index.m3u8",
+            settings: &Settings::default(),
+        };
+        let err = finalize_files(
+            &dirs,
+            "cam1",
+            FinalizeOpts::default(),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("is not a directory"), "{err}");
+        // nothing was moved
+        assert!(pending_dir.join("cam1.m3u8").is_file());
+        assert!(pending_dir.join("cam1_000.ts").is_file());
+        assert!(finished_dir.join("cam1").is_file());
+    }
+
+    #[tokio::test]
+    async fn finalize_right_after_stop_keeps_the_last_segment() {
+        let dir = TempDir::new();
+        let state = testutil::app_state(dir.path(), Config::default()).await;
+        write_pending(&state.pending_dir, "cam1", &["cam1_000.ts"]);
+
+        // stands in for ffmpeg, which only lists the segment it is writing
+        // once it has been asked to stop
+        let req: StartReq = serde_json::from_value(serde_json::json!({
+            "name": "cam1",
+            "input_url": "http://example.com/live.m3u8",
+        }))
+        .unwrap();
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let (ended_tx, ended_rx) = oneshot::channel::<()>();
+        state
+            .manager
+            .start(req, stop_tx, ended_rx, 0)
+            .await
+            .unwrap();
+        let pending_dir = state.pending_dir.clone();
+        tokio::spawn(async move {
+            let _ = stop_rx.await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            write_pending(&pending_dir, "cam1", &["cam1_000.ts", "cam1_001.ts"]);
+            drop(ended_tx);
+        });
+
+        finalize_to_vod(&state, "cam1", FinalizeOpts::default())
+            .await
+            .unwrap();
+
+        let vod_dir = state.finished_dir.join("cam1");
+        let vod = Playlist::parse(
+            &std::fs::read_to_string(vod_dir.join(&state.config.vod_playlist)).unwrap(),
+        );
+        let uris: Vec<_> = vod.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(uris, ["cam1_000.ts", "cam1_001.ts"]);
+        assert!(vod_dir.join("cam1_001.ts").is_file());
+    }
+
+    #[tokio::test]
+    async fn cancelled_finalize_restores_grace_copies() {
+        let dir = TempDir::new();
+        let pending_dir = dir.path().join("pending");
+        let finished_dir = dir.path().join("finished");
+        std::fs::create_dir_all(&pending_dir).unwrap();
+        std::fs::create_dir_all(&finished_dir).unwrap();
+        write_pending(&pending_dir, "cam1", &["cam1_000.ts", "cam1_001.ts"]);
+
+        let settings = Settings {
+            finalize_grace_secs: 60,
+            ..Default::default()
+        };
+        let dirs = FinalizeDirs {
+            pending_dir: &pending_dir,
+            finished_dir: &finished_dir,
+            vod_playlist: "index.m3u8",
+            settings: &settings,
+        };
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let err = finalize_files(&dirs, "cam1", FinalizeOpts::default(), &cancel)
+            .await
+            .unwrap_err();
+        assert!(err.is::<Cancelled>());
+        for file in ["cam1.m3u8", "cam1_000.ts", "cam1_001.ts"] {
+            assert!(pending_dir.join(file).is_file(), "{file}");
+        }
+        assert!(!finished_dir.join("cam1").exists());
+    }
+
+    #[tokio::test]
+    async fn failed_move_restores_segments_so_finalize_can_be_retried() {
+        let dir = TempDir::new();
+        let pending_dir = dir.path().join("pending");
+        let finished_dir = dir.path().join("finished");
+        std::fs::create_dir_all(&pending_dir).unwrap();
+        std::fs::create_dir_all(&finished_dir).unwrap();
+        write_pending(&pending_dir, "cam1", &["cam1_000.ts", "cam1_001.ts"]);
+        std::fs::remove_file(pending_dir.join("cam1_001.ts")).unwrap();
+
+        let settings = Settings::default();
+        let dirs = FinalizeDirs {
+            pending_dir: &pending_dir,
+            finished_dir: &finished_dir,
+            vod_playlist: "index.m3u8",
+            settings: &settings,
+        };
+        let cancel = CancellationToken::new();
+        finalize_files(&dirs, "cam1", FinalizeOpts::default(), &cancel)
+            .await
+            .unwrap_err();
+        assert!(pending_dir.join("cam1_000.ts").is_file());
+        assert!(!finished_dir.join("cam1").exists());
+
+        std::fs::write(pending_dir.join("cam1_001.ts"), b"ts").unwrap();
+        finalize_files(&dirs, "cam1", FinalizeOpts::default(), &cancel)
+            .await
+            .unwrap();
+        assert!(finished_dir.join("cam1/cam1_001.ts").is_file());
+    }
+
+    #[tokio::test]
+    async fn failed_side_step_rolls_back_the_vod_playlist() {
+        let dir = TempDir::new();
+        let pending_dir = dir.path().join("pending");
+        let finished_dir = dir.path().join("finished");
+        std::fs::create_dir_all(&pending_dir).unwrap();
+        std::fs::create_dir_all(&finished_dir).unwrap();
+        write_pending(&pending_dir, "cam1", &["cam1_000.ts"]);
+        // the I-frame playlist points at a segment that is gone
+        std::fs::write(
+            iframe_playlist(&pending_dir, "cam1"),
+            "#EXTM3U\n#EXT-X-I-FRAMES-ONLY\n#EXTINF:6.000000,\ncam1_iframe_000.ts\n",
+        )
+        .unwrap();
+
+        let settings = Settings::default();
+        let dirs = FinalizeDirs {
+            pending_dir: &pending_dir,
+            finished_dir: &finished_dir,
+            vod_playlist: "index.m3u8",
+            settings: &settings,
+        };
+        finalize_files(
+            &dirs,
+            "cam1",
+            FinalizeOpts::default(),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap_err();
+        assert!(pending_dir.join("cam1_000.ts").is_file());
+        assert!(pending_dir.join("cam1.m3u8").is_file());
+        assert!(iframe_playlist(&pending_dir, "cam1").is_file());
+        assert!(!finished_dir.join("cam1").exists());
+    }
+
+    #[tokio::test]
+    async fn grace_cleanup_survives_a_restart() {
+        let dir = TempDir::new();
+        let mut config = Config::default();
+        config.settings.finalize_grace_secs = 60;
+        let state = testutil::app_state(dir.path(), config).await;
+        write_pending(&state.pending_dir, "cam1", &["cam1_000.ts", "cam1_001.ts"]);
+
+        let settings = state.settings.load();
+        let dirs = FinalizeDirs {
+            pending_dir: &state.pending_dir,
+            finished_dir: &state.finished_dir,
+            vod_playlist: "index.m3u8",
+            settings: &settings,
+        };
+        finalize_files(
+            &dirs,
+            "cam1",
+            FinalizeOpts::default(),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        let cleanup = cleanup_path(&state.pending_dir, "cam1");
+        assert!(cleanup.is_file());
+        assert!(state.pending_dir.join("cam1_000.ts").is_file());
+
+        // as if the grace period ran out while the server was down
+        let mut saved: Cleanup = serde_json::from_slice(&std::fs::read(&cleanup).unwrap()).unwrap();
+        saved.finalized -= 60;
+        std::fs::write(&cleanup, serde_json::to_vec(&saved).unwrap()).unwrap();
+        run_cleanup(&state, "cam1").await;
+        assert!(!state.pending_dir.join("cam1_000.ts").exists());
+        assert!(!state.pending_dir.join("cam1.m3u8").exists());
+        assert!(!cleanup.exists());
+        assert!(state.finished_dir.join("cam1/cam1_000.ts").is_file());
+    }
+}
@@ -0,0 +1,142 @@
+//! Correlation IDs for requests.
+//!
+//! Every request carries an `X-Request-ID`: the client's own if it sent a
+//! usable one, otherwise a generated UUID. The ID is part of the request's
+//! tracing span (so every log line of the request shows it), is echoed in
+//! the response header and is added to JSON error bodies.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::{Body, HttpBody, to_bytes},
+    extract::Request,
+    http::{HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Span;
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+/// Longer client IDs are replaced by a generated one
+const MAX_ID_LEN: usize = 128;
+/// Error bodies are small; anything larger is passed through unchanged
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+pub async fn request_id(mut req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(&X_REQUEST_ID)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_ID_LEN && v.to_str().is_ok())
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_str(&new_id()).expect("hex and dashes"));
+    req.headers_mut().insert(X_REQUEST_ID.clone(), id.clone());
+
+    let mut resp = next.run(req).await;
+    if resp.status().is_client_error() || resp.status().is_server_error() {
+        resp = add_to_json_error(resp, id.to_str().unwrap_or_default()).await;
+    }
+    resp.headers_mut().insert(X_REQUEST_ID.clone(), id);
+    resp
+}
+
+/// Span for `TraceLayer` with the ID set by `request_id`.
+pub fn make_span(req: &Request) -> Span {
+    let id = req
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        request_id = %id,
+    )
+}
+
+/// `{"error": ...}` becomes `{"error": ..., "request_id": ...}`; other
+/// bodies are left alone.
+async fn add_to_json_error(resp: Response, id: &str) -> Response {
+    let is_json = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    // handlers' bodies know their size; hyper only sets Content-Length later
+    let small = resp
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|len| len <= MAX_ERROR_BODY as u64);
+    if !is_json || !small {
+        return resp;
+    }
+    let (mut parts, body) = resp.into_parts();
+    let Ok(bytes) = to_bytes(body, MAX_ERROR_BODY).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut obj)) => {
+            obj.insert("request_id".into(), id.into());
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(serde_json::Value::Object(obj).to_string())
+        }
+        _ => Body::from(bytes),
+    };
+    Response::from_parts(parts, body)
+}
+
+/// A random (version 4) UUID. `RandomState` is seeded randomly per thread
+/// and advanced for every instance; time and a counter are mixed in as well.
+fn new_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let half = |salt: u64| {
+        let mut h = RandomState::new().build_hasher();
+        h.write_u64(salt);
+        h.write_u64(count);
+        h.write_u128(nanos);
+        h.finish() as u128
+    };
+    let mut v = (half(0) << 64) | half(1);
+    v = (v & !(0xf << 76)) | (0x4 << 76);
+    v = (v & !(0x3 << 62)) | (0x2 << 62);
+    let hex = format!("{:032x}", v);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, http::StatusCode, response::IntoResponse};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn json_error_gets_the_request_id() {
+        let resp = (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "Recording not found"})),
+        )
+            .into_response();
+        let resp = add_to_json_error(resp, "abc").await;
+        let bytes = to_bytes(resp.into_body(), MAX_ERROR_BODY).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["request_id"], "abc");
+        assert_eq!(body["error"], "Recording not found");
+    }
+}
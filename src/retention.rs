@@ -0,0 +1,105 @@
+//! Deletes finished recordings once their retention period is over.
+//!
+//! A recording keeps the `retention_days` it was started with (stored in
+//! its `meta.json`); recordings without one use the global setting. 0 in
+//! either place means the recording is never deleted automatically. The
+//! age is taken from the VOD playlist, which is written on finalize.
+
+use std::time::SystemTime;
+
+use tokio::{
+    fs,
+    time::{Duration, interval},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    recording::remove_artifact,
+    state::{AppState, FinishedEntry},
+};
+
+/// Retention is counted in days, checking more often gains nothing.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(3600);
+pub const DAY: Duration = Duration::from_secs(24 * 3600);
+
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        let mut tick = interval(SWEEP_INTERVAL);
+        loop {
+            tick.tick().await;
+            sweep(&state).await;
+        }
+    });
+}
+
+async fn sweep(state: &AppState) {
+    let default_days = state.settings.load().retention_days;
+    let mut removed = false;
+    for entry in state.finished_entries().await.iter() {
+        let days = entry.meta.retention_days.unwrap_or(default_days);
+        if days == 0 {
+            continue;
+        }
+        let Some(age) = age(state, entry).await else {
+            continue;
+        };
+        if age < DAY * days {
+            continue;
+        }
+        info!(name = %entry.name, days, "retention period over - deleting recording");
+        remove_finished(state, entry).await;
+        removed = true;
+    }
+    if removed {
+        state.invalidate_finished().await;
+    }
+}
+
+/// Time since the recording was finalized, from its VOD playlist.
+pub async fn age(state: &AppState, entry: &FinishedEntry) -> Option<Duration> {
+    let playlist = state.finished_dir.join(&entry.name).join(&entry.playlist);
+    let finished_at = fs::metadata(&playlist)
+        .await
+        .and_then(|m| m.modified())
+        .ok()?;
+    Some(
+        SystemTime::now()
+            .duration_since(finished_at)
+            .unwrap_or_default(),
+    )
+}
+
+/// Delete the files of a finished recording and its folder. Files outside
+/// the cleanup allowlist that are not our own outputs are left in place.
+/// Returns the bytes freed.
+pub async fn remove_finished(state: &AppState, entry: &FinishedEntry) -> u64 {
+    let settings = state.settings.load();
+    let dir = state.finished_dir.join(&entry.name);
+    let mut freed = 0;
+    let Ok(mut rd) = fs::read_dir(&dir).await else {
+        return freed;
+    };
+    while let Ok(Some(file)) = rd.next_entry().await {
+        let path = file.path();
+        let f = file.file_name().to_string_lossy().into_owned();
+        let size = file.metadata().await.map_or(0, |m| m.len());
+        let removed =
+            if f == "meta.json" || entry.meta.audio.contains(&f) || entry.meta.archive.contains(&f)
+            {
+                let removed = fs::remove_file(&path).await;
+                if let Err(e) = &removed {
+                    error!(file=?path, error=?e, "failed to remove recording file");
+                }
+                removed.is_ok()
+            } else {
+                remove_artifact(&settings, &path).await.is_ok()
+            };
+        if removed {
+            freed += size;
+        }
+    }
+    if fs::remove_dir(&dir).await.is_err() {
+        warn!(name = %entry.name, "recording folder kept, it still contains other files");
+    }
+    freed
+}
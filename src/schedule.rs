@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::state::unix_now;
+
+const DAY_SECS: u32 = 24 * 60 * 60;
+
+/// A daily time range (`HH:MM`, UTC) during which a recording is paused.
+/// `end` before `start` spans midnight.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PauseWindow {
+    pub start: String,
+    pub end: String,
+}
+
+impl PauseWindow {
+    fn bounds(&self) -> Result<(u32, u32)> {
+        Ok((
+            parse_time_of_day(&self.start)?,
+            parse_time_of_day(&self.end)?,
+        ))
+    }
+}
+
+pub fn check(windows: &[PauseWindow]) -> Result<()> {
+    for w in windows {
+        let (start, end) = w.bounds()?;
+        if start == end {
+            anyhow::bail!("pause window {}-{} is empty", w.start, w.end);
+        }
+    }
+    Ok(())
+}
+
+/// Seconds until the pause window we are in ends, `None` outside of all
+/// windows. Adjacent windows are handled by checking again afterwards.
+pub fn paused_for(windows: &[PauseWindow]) -> Option<u32> {
+    let now = (unix_now() % DAY_SECS as u64) as u32;
+    windows
+        .iter()
+        .filter_map(|w| w.bounds().ok())
+        .filter_map(|(start, end)| {
+            let inside = if start < end {
+                (start..end).contains(&now)
+            } else {
+                now >= start || now < end
+            };
+            inside.then(|| (end + DAY_SECS - now) % DAY_SECS)
+        })
+        .max()
+}
+
+/// Seconds until the next pause window starts.
+pub fn next_pause_in(windows: &[PauseWindow]) -> Option<u32> {
+    let now = (unix_now() % DAY_SECS as u64) as u32;
+    windows
+        .iter()
+        .filter_map(|w| w.bounds().ok())
+        .map(|(start, _)| (start + DAY_SECS - now) % DAY_SECS)
+        .filter(|secs| *secs > 0)
+        .min()
+}
+
+fn parse_time_of_day(s: &str) -> Result<u32> {
+    let (h, m) = s
+        .split_once(':')
+        .with_context(|| format!("invalid time of day {:?}, expected HH:MM", s))?;
+    let (Ok(h), Ok(m)) = (h.parse::<u32>(), m.parse::<u32>()) else {
+        anyhow::bail!("invalid time of day {:?}, expected HH:MM", s);
+    };
+    if h > 23 || m > 59 {
+        anyhow::bail!("invalid time of day {:?}, expected HH:MM", s);
+    }
+    Ok((h * 60 + m) * 60)
+}
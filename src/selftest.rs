@@ -0,0 +1,147 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::fs;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use crate::{
+    command::{CommandSpec, SidecarOutputs},
+    playlist::parse_segments,
+    recording::{FinalizeDirs, FinalizeOpts, StartReq, finalize_files},
+    state::{AppState, unix_now},
+};
+
+const NAME: &str = "selftest";
+/// Length of the synthetic recording in seconds
+const TEST_SECONDS: u32 = 10;
+const HLS_TIME: u32 = 2;
+
+#[derive(Serialize)]
+pub struct SelftestReport {
+    pub ok: bool,
+    pub segments: usize,
+    pub duration_secs: f64,
+    pub error: Option<String>,
+}
+
+/// Record a few seconds of ffmpeg's `testsrc`, finalize it and check the
+/// resulting VOD. Runs in a scratch directory below the base directory
+/// that is removed afterwards, so real recordings are never touched.
+pub async fn run(state: &AppState) -> SelftestReport {
+    let base = state.config.base_dir.clone().unwrap_or_default();
+    let scratch = base.join(format!(".selftest-{}", unix_now()));
+    let result = record_and_check(state, &scratch).await;
+    if let Err(e) = fs::remove_dir_all(&scratch).await {
+        error!(dir=?scratch, error=?e, "failed to remove selftest directory");
+    }
+    match result {
+        Ok((segments, duration_secs)) => {
+            info!(segments, duration_secs, "selftest passed");
+            SelftestReport {
+                ok: true,
+                segments,
+                duration_secs,
+                error: None,
+            }
+        }
+        Err(e) => {
+            error!(error=?e, "selftest failed");
+            SelftestReport {
+                ok: false,
+                segments: 0,
+                duration_secs: 0.0,
+                error: Some(format!("{:#}", e)),
+            }
+        }
+    }
+}
+
+async fn record_and_check(state: &AppState, scratch: &Path) -> Result<(usize, f64)> {
+    let pending_dir = scratch.join("pending");
+    let finished_dir = scratch.join("finished");
+    fs::create_dir_all(&pending_dir).await?;
+    fs::create_dir_all(&finished_dir).await?;
+
+    let source = format!("testsrc=duration={}:size=640x360:rate=25", TEST_SECONDS);
+    let req: StartReq = serde_json::from_value(serde_json::json!({
+        "name": NAME,
+        "input_url": source,
+        "hls_time": HLS_TIME,
+        // testsrc is raw video, which cannot be stream-copied into MPEG-TS
+        "transcode": {"preset": "ultrafast"},
+    }))?;
+    let spec = CommandSpec {
+        req,
+        input_url: source,
+        input_format: Some("lavfi".to_string()),
+        pending_dir: pending_dir.clone(),
+        watermark: None,
+        fifo: None,
+        audio_tracks: 0,
+        no_audio: None,
+    };
+
+    // 1) record until the source ends
+    let mut cmd = spec.build(&SidecarOutputs::default());
+    info!("Starting selftest ffmpeg: {}", spec.display(&cmd));
+    let timeout = Duration::from_secs(TEST_SECONDS as u64 * 3);
+    let out = tokio::time::timeout(timeout, cmd.output())
+        .await
+        .context("ffmpeg did not finish in time")?
+        .context("ffmpeg could not be started")?;
+    if !out.status.success() {
+        anyhow::bail!(
+            "ffmpeg failed with status {}: {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+
+    // 2) finalize
+    let settings = state.settings.load();
+    let dirs = FinalizeDirs {
+        pending_dir: &pending_dir,
+        finished_dir: &finished_dir,
+        vod_playlist: &state.config.vod_playlist,
+        settings: &settings,
+    };
+    finalize_files(
+        &dirs,
+        NAME,
+        FinalizeOpts::default(),
+        &CancellationToken::new(),
+    )
+    .await?;
+
+    // 3) check the VOD
+    let vod_dir = finished_dir.join(NAME);
+    let playlist = fs::read_to_string(vod_dir.join(&state.config.vod_playlist))
+        .await
+        .context("VOD playlist was not written")?;
+    if !playlist.contains("#EXT-X-ENDLIST") {
+        anyhow::bail!("VOD playlist has no ENDLIST");
+    }
+    let segments = parse_segments(&playlist);
+    if segments.is_empty() {
+        anyhow::bail!("VOD playlist lists no segments");
+    }
+    for seg in &segments {
+        let md = fs::metadata(vod_dir.join(&seg.uri))
+            .await
+            .with_context(|| format!("segment {} is missing", seg.uri))?;
+        if md.len() == 0 {
+            anyhow::bail!("segment {} is empty", seg.uri);
+        }
+    }
+    let duration: f64 = segments.iter().map(|s| s.duration).sum();
+    if duration < (TEST_SECONDS - HLS_TIME) as f64 {
+        anyhow::bail!(
+            "VOD is only {:.1}s long, expected about {}s",
+            duration,
+            TEST_SECONDS
+        );
+    }
+    Ok((segments.len(), duration))
+}
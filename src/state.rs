@@ -1,41 +1,299 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use crate::recording::StartReq;
+use crate::{
+    config::{Config, DEFAULT_VOD_PLAYLIST, SettingsHandle},
+    diskmon::DiskMonitor,
+    events::SegmentEvent,
+    jobs::JobRegistry,
+    meta::{self, RecordingMeta},
+    queue::StartQueue,
+    recording::StartReq,
+};
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use tokio::{
     fs,
-    sync::{oneshot, Mutex},
+    process::ChildStdin,
+    sync::{Mutex, broadcast, oneshot},
 };
+use tracing::{error, info, warn};
+
+/// Recently extracted snapshots: name -> (taken at, JPEG bytes)
+pub type SnapshotCache = Mutex<HashMap<String, (Instant, Vec<u8>)>>;
+/// Recent ffprobe results: input URL -> (probed at, result)
+pub type ProbeCache = Mutex<HashMap<String, (Instant, serde_json::Value)>>;
+/// Finished recordings with the mtime of the finished directory when they
+/// were listed
+pub type FinishedCache = Mutex<Option<(SystemTime, Arc<Vec<FinishedEntry>>)>>;
+
+/// A finalized recording as found in the finished directory.
+pub struct FinishedEntry {
+    pub name: String,
+    /// File name of the VOD playlist
+    pub playlist: String,
+    pub meta: RecordingMeta,
+}
 
 #[derive(Clone)]
 pub struct AppState {
     pub pending_dir: PathBuf,
     pub finished_dir: PathBuf,
+    /// Per-recording ffmpeg logs, see `reclog`
+    pub logs_dir: PathBuf,
     pub manager: Arc<RecordingManager>,
+    /// Starts waiting for a free slot, see `queue`
+    pub queue: Arc<StartQueue>,
+    /// Configuration as resolved at startup
+    pub config: Arc<Config>,
+    pub settings: Arc<SettingsHandle>,
+    pub snapshots: Arc<SnapshotCache>,
+    pub probes: Arc<ProbeCache>,
+    pub finished: Arc<FinishedCache>,
+    pub jobs: Arc<JobRegistry>,
+    pub disk: Arc<DiskMonitor>,
+    /// New segments of all recordings, see `events`
+    pub segment_events: broadcast::Sender<SegmentEvent>,
+    /// When the server started, for uptime reporting
+    pub started: Instant,
+    /// No new recordings are started while set, see `/api/maintenance`
+    pub maintenance: Arc<AtomicBool>,
+}
+
+impl AppState {
+    /// `path` (e.g. `/vod/{name}/index.m3u8`) below the configured base path.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.base_path, path)
+    }
+
+    /// File name of a finished recording's playlist: the configured name,
+    /// or `index.m3u8` for recordings finalized before it was changed.
+    pub async fn find_vod_playlist(&self, name: &str) -> Option<&str> {
+        let dir = self.finished_dir.join(name);
+        for candidate in [self.config.vod_playlist.as_str(), DEFAULT_VOD_PLAYLIST] {
+            if fs::metadata(dir.join(candidate)).await.is_ok() {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    /// All finalized recordings. The directory is only read again when its
+    /// mtime changed (recordings added or removed) or after
+    /// `invalidate_finished`, so large archives list quickly.
+    pub async fn finished_entries(&self) -> Arc<Vec<FinishedEntry>> {
+        let mtime = fs::metadata(&self.finished_dir)
+            .await
+            .and_then(|m| m.modified())
+            .ok();
+        // held while listing so concurrent callers wait for one listing
+        let mut cache = self.finished.lock().await;
+        if let (Some(mtime), Some((listed_at, entries))) = (mtime, cache.as_ref())
+            && *listed_at == mtime
+        {
+            return entries.clone();
+        }
+        let entries = Arc::new(self.list_finished_dir().await);
+        *cache = mtime.map(|m| (m, entries.clone()));
+        entries
+    }
+
+    pub fn in_maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::Relaxed)
+    }
+
+    /// Drop the cached listing after changes inside recording folders.
+    pub async fn invalidate_finished(&self) {
+        *self.finished.lock().await = None;
+    }
+
+    async fn list_finished_dir(&self) -> Vec<FinishedEntry> {
+        let mut entries = Vec::new();
+        let Ok(mut rd) = fs::read_dir(&self.finished_dir).await else {
+            return entries;
+        };
+        while let Ok(Some(entry)) = rd.next_entry().await {
+            if !entry.file_type().await.is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            let Some(playlist) = self.find_vod_playlist(&name).await else {
+                continue;
+            };
+            let playlist = playlist.to_string();
+            let meta = meta::load(&meta::finished_path(&self.finished_dir, &name)).await;
+            entries.push(FinishedEntry {
+                name,
+                playlist,
+                meta,
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries
+    }
 }
 
 pub struct RecordingManager {
     // name -> control
     inner: Mutex<HashMap<String, RecordingControl>>,
+    // name -> why it ended
+    failed: Mutex<HashMap<String, FailedRecording>>,
+    next_run: AtomicU64,
+    /// ffmpeg restarts after errors since the server started
+    restarts: AtomicU64,
     persist_path: PathBuf,
+    failed_path: PathBuf,
+    /// Names whose recording task may still have ffmpeg writing to the
+    /// pending files, see `claim_writer`
+    writers: Arc<std::sync::Mutex<HashSet<String>>>,
 }
 
 struct RecordingControl {
     stop: Option<oneshot::Sender<()>>,
     req: StartReq,
+    /// PID of the current ffmpeg child, if one is running
+    pid: Option<u32>,
+    /// Identifies this run so a late exit of a previous task with the same
+    /// name cannot remove a newer entry
+    run: u64,
+    /// Last sign of life from the supervising task
+    heartbeat: Instant,
+    /// End of ffmpeg's stderr from the last failed run
+    last_error: Option<String>,
+    /// Closed once the task has stopped ffmpeg and cleaned up
+    ended: oneshot::Receiver<()>,
+    registered: Instant,
+    /// Last rewrite of the live playlist seen by the supervising task
+    last_segment: Option<SystemTime>,
+    /// Last request for the live playlist, see `auto_stop_idle_secs`
+    last_viewed: Instant,
+    /// ffmpeg restarts after errors during this run
+    restarts: u32,
+    /// stdin of an ffmpeg reading an ingested stream, until an ingest
+    /// request takes it
+    stdin: Option<ChildStdin>,
+}
+
+/// A recording whose supervising task ended abnormally.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FailedRecording {
+    pub req: StartReq,
+    pub reason: String,
+    /// End of ffmpeg's stderr from the last failed ffmpeg run
+    #[serde(default)]
+    pub last_error: Option<String>,
+    /// Unix timestamp (seconds)
+    pub failed_at: u64,
+    /// ffmpeg restarts before the recording failed
+    #[serde(default)]
+    pub restarts: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthState {
+    Running,
+    /// ffmpeg is not running, waiting for a restart or a pause window
+    Restarting,
+    /// ffmpeg runs, but no new segment for `STALL_SEGMENTS` segment durations
+    Stalled,
+    Failed,
+}
+
+#[derive(Serialize)]
+pub struct RecordingHealth {
+    pub name: String,
+    pub state: HealthState,
+    pub seconds_since_last_segment: Option<u64>,
+    pub restarts: u32,
+    pub error: Option<String>,
+}
+
+/// Returned by `RecordingManager::start` when `max_concurrent` recordings
+/// are running.
+#[derive(Debug, thiserror::Error)]
+#[error("Too many recordings running (limit {limit})")]
+pub struct AtCapacity {
+    pub limit: usize,
+}
+
+/// Returned by `RecordingManager::claim_writer` while an earlier run of the
+/// same recording is still shutting down.
+#[derive(Debug, thiserror::Error)]
+#[error("Recording '{name}' is still being written by a previous run; retry once it has stopped")]
+pub struct WriterBusy {
+    pub name: String,
 }
 
+/// The right to write a recording's pending files; released on drop.
+pub struct WriterLock {
+    name: String,
+    writers: Arc<std::sync::Mutex<HashSet<String>>>,
+}
+
+impl Drop for WriterLock {
+    fn drop(&mut self) {
+        self.writers.lock().unwrap().remove(&self.name);
+    }
+}
+
+/// Format of the persisted active recordings. 0 was a bare list; 1 wraps
+/// it as `{"version": 1, "recordings": [...]}`. Fields added to `StartReq`
+/// need a serde default so older files still load.
+const PERSIST_VERSION: u64 = 1;
+
+/// How often the supervising task reports that it is alive
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Entries without a heartbeat for this long are considered dead
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Missing segment durations after which a running recording is stalled
+const STALL_SEGMENTS: u64 = 3;
+
 impl RecordingManager {
     pub fn new(persist_path: PathBuf) -> Self {
         Self {
             inner: Mutex::new(HashMap::new()),
+            failed: Mutex::new(HashMap::new()),
+            next_run: AtomicU64::new(0),
+            restarts: AtomicU64::new(0),
+            failed_path: persist_path.with_file_name("failed_recordings.json"),
             persist_path,
+            writers: Default::default(),
         }
     }
 
+    /// Reserve `name` for one writer. `stop` frees the name before ffmpeg
+    /// has exited, so a start (or resume) right after it could otherwise
+    /// run a second ffmpeg on the same playlist. The lock is held until the
+    /// recording task has ended.
+    pub fn claim_writer(&self, name: &str) -> Result<WriterLock> {
+        let mut writers = self.writers.lock().unwrap();
+        if !writers.insert(name.to_string()) {
+            return Err(WriterBusy {
+                name: name.to_string(),
+            }
+            .into());
+        }
+        Ok(WriterLock {
+            name: name.to_string(),
+            writers: self.writers.clone(),
+        })
+    }
+
     async fn save(&self, map: &HashMap<String, RecordingControl>) -> Result<()> {
-        let list: Vec<&StartReq> = map.values().map(|c| &c.req).collect();
-        let json = serde_json::to_string(&list)?;
+        let json = serde_json::to_string(&serde_json::json!({
+            "version": PERSIST_VERSION,
+            "recordings": map.values().map(|c| &c.req).collect::<Vec<_>>(),
+        }))?;
         if let Some(parent) = self.persist_path.parent() {
             fs::create_dir_all(parent).await.ok();
         }
@@ -43,29 +301,167 @@ impl RecordingManager {
         Ok(())
     }
 
+    /// Recordings that were running when the server stopped. Files of older
+    /// versions are migrated; a recording that cannot be read is skipped
+    /// with an error instead of failing the whole recovery.
     pub async fn load(&self) -> Result<Vec<StartReq>> {
-        match fs::read_to_string(&self.persist_path).await {
-            Ok(content) => Ok(serde_json::from_str(&content)?),
-            Err(_) => Ok(Vec::new()),
+        let Ok(content) = fs::read_to_string(&self.persist_path).await else {
+            return Ok(Vec::new());
+        };
+        let entries = match serde_json::from_str::<serde_json::Value>(&content)? {
+            // version 0: a bare list of start requests
+            serde_json::Value::Array(entries) => entries,
+            serde_json::Value::Object(mut envelope) => {
+                let version = envelope
+                    .get("version")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or_default();
+                if version > PERSIST_VERSION {
+                    anyhow::bail!(
+                        "{} was written by a newer version (format {}, supported up to {})",
+                        self.persist_path.display(),
+                        version,
+                        PERSIST_VERSION
+                    );
+                }
+                match envelope.remove("recordings") {
+                    Some(serde_json::Value::Array(entries)) => entries,
+                    _ => anyhow::bail!("{} has no recordings list", self.persist_path.display()),
+                }
+            }
+            _ => anyhow::bail!("{} is not a recordings list", self.persist_path.display()),
+        };
+        let mut reqs = Vec::new();
+        for entry in entries {
+            let name = entry.get("name").cloned();
+            match serde_json::from_value::<StartReq>(entry) {
+                Ok(req) => reqs.push(req),
+                Err(e) => error!(?name, error=?e, "skipping unreadable persisted recording"),
+            }
+        }
+        Ok(reqs)
+    }
+
+    async fn save_failed(&self, failed: &HashMap<String, FailedRecording>) {
+        let result = async {
+            fs::write(&self.failed_path, serde_json::to_string(failed)?).await?;
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            error!(file=?self.failed_path, error=?e, "failed to save failed recordings");
         }
     }
 
-    pub async fn start(&self, req: StartReq, stop: oneshot::Sender<()>) -> Result<()> {
+    /// Restore the failed recordings of a previous server run.
+    pub async fn load_failed(&self) -> Result<()> {
+        if let Ok(content) = fs::read_to_string(&self.failed_path).await {
+            *self.failed.lock().await = serde_json::from_str(&content)?;
+        }
+        Ok(())
+    }
+
+    /// Failed recordings, most recent first.
+    pub async fn failed_list(&self) -> Vec<FailedRecording> {
+        let mut list: Vec<FailedRecording> = self.failed.lock().await.values().cloned().collect();
+        list.sort_by_key(|f| std::cmp::Reverse(f.failed_at));
+        list
+    }
+
+    pub async fn failed_entry(&self, name: &str) -> Option<FailedRecording> {
+        self.failed.lock().await.get(name).cloned()
+    }
+
+    /// Forget a failed recording; returns `false` if there was none.
+    pub async fn clear_failed(&self, name: &str) -> bool {
+        let mut failed = self.failed.lock().await;
+        let removed = failed.remove(name).is_some();
+        if removed {
+            self.save_failed(&failed).await;
+        }
+        removed
+    }
+
+    /// Register a recording; returns the run id its task reports with.
+    pub async fn start(
+        &self,
+        req: StartReq,
+        stop: oneshot::Sender<()>,
+        ended: oneshot::Receiver<()>,
+        max_concurrent: usize,
+    ) -> Result<u64> {
         let mut map = self.inner.lock().await;
         if map.contains_key(&req.name) {
             anyhow::bail!("Recording '{}' is already running", req.name);
         }
+        if max_concurrent > 0 && map.len() >= max_concurrent {
+            return Err(AtCapacity {
+                limit: max_concurrent,
+            }
+            .into());
+        }
+        let run = self.next_run.fetch_add(1, Ordering::Relaxed) + 1;
+        {
+            let mut failed = self.failed.lock().await;
+            if failed.remove(&req.name).is_some() {
+                self.save_failed(&failed).await;
+            }
+        }
         map.insert(
             req.name.clone(),
             RecordingControl {
                 stop: Some(stop),
                 req,
+                pid: None,
+                run,
+                heartbeat: Instant::now(),
+                last_error: None,
+                ended,
+                registered: Instant::now(),
+                last_segment: None,
+                last_viewed: Instant::now(),
+                restarts: 0,
+                stdin: None,
             },
         );
-        self.save(&map).await
+        self.save(&map).await?;
+        Ok(run)
+    }
+
+    /// Returns `false` if no recording with that name was running.
+    pub async fn stop(&self, name: &str) -> Result<bool> {
+        let mut map = self.inner.lock().await;
+        let Some(mut ctrl) = map.remove(name) else {
+            return Ok(false);
+        };
+        if let Some(tx) = ctrl.stop.take() {
+            let _ = tx.send(());
+        }
+        self.save(&map).await?;
+        Ok(true)
+    }
+
+    /// Like `stop`, but only returns once the recording's task has ended,
+    /// so its files can be taken over by a new run.
+    pub async fn stop_and_wait(&self, name: &str) -> Result<bool> {
+        let ended = {
+            let mut map = self.inner.lock().await;
+            let Some(mut ctrl) = map.remove(name) else {
+                return Ok(false);
+            };
+            if let Some(tx) = ctrl.stop.take() {
+                let _ = tx.send(());
+            }
+            self.save(&map).await?;
+            ctrl.ended
+        };
+        let _ = ended.await;
+        Ok(true)
     }
 
-    pub async fn stop(&self, name: &str) -> Result<()> {
+    /// Like `stop`, but hands back the PID of the running ffmpeg so the
+    /// caller can kill it directly. The name is free again right away.
+    pub async fn kill(&self, name: &str) -> Result<Option<u32>> {
         let mut map = self.inner.lock().await;
         let mut ctrl = match map.remove(name) {
             Some(ctrl) => ctrl,
@@ -75,18 +471,282 @@ impl RecordingManager {
             let _ = tx.send(());
         }
         self.save(&map).await?;
-        Ok(())
+        Ok(ctrl.pid)
     }
 
-    pub async fn finish(&self, name: &str) {
+    pub async fn finish(&self, name: &str, run: u64) {
         let mut map = self.inner.lock().await;
-        if map.remove(name).is_some() {
+        if map.get(name).is_some_and(|c| c.run == run) {
+            map.remove(name);
+            let _ = self.save(&map).await;
+        }
+    }
+
+    /// Like `finish`, but remembers the recording as failed.
+    pub async fn fail(&self, name: &str, run: u64, reason: &str) {
+        let mut map = self.inner.lock().await;
+        if map.get(name).is_none_or(|c| c.run != run) {
+            return;
+        }
+        if let Some(mut ctrl) = map.remove(name) {
+            if let Some(tx) = ctrl.stop.take() {
+                let _ = tx.send(());
+            }
             let _ = self.save(&map).await;
+            error!(%name, %reason, "recording failed");
+            let mut failed = self.failed.lock().await;
+            failed.insert(
+                name.to_string(),
+                FailedRecording {
+                    req: ctrl.req,
+                    reason: reason.to_string(),
+                    last_error: ctrl.last_error,
+                    failed_at: unix_now(),
+                    restarts: ctrl.restarts,
+                },
+            );
+            self.save_failed(&failed).await;
+        }
+    }
+
+    pub async fn heartbeat(&self, name: &str, run: u64) {
+        let mut map = self.inner.lock().await;
+        if let Some(ctrl) = map.get_mut(name).filter(|c| c.run == run) {
+            ctrl.heartbeat = Instant::now();
+        }
+    }
+
+    /// Periodically mark recordings whose task stopped reporting as failed
+    /// and stop those nobody watched for their `auto_stop_idle_secs`.
+    pub fn spawn_watchdog(self: &Arc<Self>) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(HEARTBEAT_TIMEOUT / 2);
+            loop {
+                tick.tick().await;
+                let stale: Vec<(String, u64)> = {
+                    let map = manager.inner.lock().await;
+                    map.iter()
+                        .filter(|(_, c)| c.heartbeat.elapsed() > HEARTBEAT_TIMEOUT)
+                        .map(|(n, c)| (n.clone(), c.run))
+                        .collect()
+                };
+                for (name, run) in stale {
+                    warn!(%name, "no heartbeat from recording task");
+                    manager
+                        .fail(&name, run, "recording task stopped responding")
+                        .await;
+                }
+                let idle: Vec<String> = {
+                    let map = manager.inner.lock().await;
+                    map.iter()
+                        .filter(|(_, c)| {
+                            c.req.auto_stop_idle_secs.is_some_and(|secs| {
+                                c.last_viewed.elapsed() > Duration::from_secs(secs)
+                            })
+                        })
+                        .map(|(n, _)| n.clone())
+                        .collect()
+                };
+                for name in idle {
+                    info!(%name, "live playlist not requested - stopping idle recording");
+                    if let Err(e) = manager.stop(&name).await {
+                        error!(%name, error=?e, "failed to stop idle recording");
+                    }
+                }
+            }
+        });
+    }
+
+    pub async fn set_last_error(&self, name: &str, run: u64, error: String) {
+        let mut map = self.inner.lock().await;
+        if let Some(ctrl) = map.get_mut(name).filter(|c| c.run == run) {
+            ctrl.last_error = Some(error);
+        }
+    }
+
+    pub async fn set_pid(&self, name: &str, run: u64, pid: Option<u32>) {
+        let mut map = self.inner.lock().await;
+        if let Some(ctrl) = map.get_mut(name).filter(|c| c.run == run) {
+            ctrl.pid = pid;
+        }
+    }
+
+    pub async fn set_stdin(&self, name: &str, run: u64, stdin: Option<ChildStdin>) {
+        let mut map = self.inner.lock().await;
+        if let Some(ctrl) = map.get_mut(name).filter(|c| c.run == run) {
+            ctrl.stdin = stdin;
         }
     }
 
+    /// Hand ffmpeg's stdin to one ingest request; closing it ends the
+    /// recording.
+    pub async fn take_stdin(&self, name: &str) -> Option<ChildStdin> {
+        let mut map = self.inner.lock().await;
+        map.get_mut(name).and_then(|c| c.stdin.take())
+    }
+
+    /// `None` if the recording is not running, `Some(None)` while ffmpeg is restarting.
+    pub async fn pid(&self, name: &str) -> Option<Option<u32>> {
+        let map = self.inner.lock().await;
+        map.get(name).map(|c| c.pid)
+    }
+
+    /// The request a running recording was started with.
+    pub async fn running_req(&self, name: &str) -> Option<StartReq> {
+        let map = self.inner.lock().await;
+        map.get(name).map(|c| c.req.clone())
+    }
+
     pub async fn is_running(&self, name: &str) -> bool {
         let map = self.inner.lock().await;
         map.contains_key(name)
     }
+
+    pub async fn running_count(&self) -> usize {
+        self.inner.lock().await.len()
+    }
+
+    pub async fn failed_count(&self) -> usize {
+        self.failed.lock().await.len()
+    }
+
+    pub async fn record_restart(&self, name: &str, run: u64) {
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+        let mut map = self.inner.lock().await;
+        if let Some(ctrl) = map.get_mut(name).filter(|c| c.run == run) {
+            ctrl.restarts += 1;
+        }
+    }
+
+    /// Someone requested the live playlist of `name`.
+    pub async fn touch(&self, name: &str) {
+        let mut map = self.inner.lock().await;
+        if let Some(ctrl) = map.get_mut(name) {
+            ctrl.last_viewed = Instant::now();
+        }
+    }
+
+    pub async fn set_last_segment(&self, name: &str, run: u64, at: SystemTime) {
+        let mut map = self.inner.lock().await;
+        if let Some(ctrl) = map.get_mut(name).filter(|c| c.run == run) {
+            ctrl.last_segment = Some(at);
+        }
+    }
+
+    /// State of every running and failed recording, from memory only.
+    pub async fn health(&self) -> Vec<RecordingHealth> {
+        let mut list: Vec<RecordingHealth> = {
+            let map = self.inner.lock().await;
+            map.iter()
+                .map(|(name, c)| {
+                    let since_segment = c
+                        .last_segment
+                        .map(|t| t.elapsed().unwrap_or_default().as_secs());
+                    let silent_for =
+                        since_segment.unwrap_or_else(|| c.registered.elapsed().as_secs());
+                    // the playlist is only looked at once per heartbeat
+                    let stall_after =
+                        STALL_SEGMENTS * u64::from(c.req.hls_time) + HEARTBEAT_INTERVAL.as_secs();
+                    let state = if c.pid.is_none() {
+                        HealthState::Restarting
+                    } else if silent_for > stall_after {
+                        HealthState::Stalled
+                    } else {
+                        HealthState::Running
+                    };
+                    RecordingHealth {
+                        name: name.clone(),
+                        state,
+                        seconds_since_last_segment: since_segment,
+                        restarts: c.restarts,
+                        error: c.last_error.clone(),
+                    }
+                })
+                .collect()
+        };
+        list.extend(
+            self.failed
+                .lock()
+                .await
+                .iter()
+                .map(|(name, f)| RecordingHealth {
+                    name: name.clone(),
+                    state: HealthState::Failed,
+                    seconds_since_last_segment: None,
+                    restarts: f.restarts,
+                    error: Some(f.reason.clone()),
+                }),
+        );
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn writer_lock_is_exclusive_until_dropped() {
+        let manager = RecordingManager::new(PathBuf::from("active_recordings.json"));
+        let lock = manager.claim_writer("cam1").unwrap();
+
+        let err = manager.claim_writer("cam1").err().unwrap();
+        assert!(err.is::<WriterBusy>());
+        // other names are not affected
+        drop(manager.claim_writer("cam2").unwrap());
+
+        drop(lock);
+        manager.claim_writer("cam1").unwrap();
+    }
+
+    #[tokio::test]
+    async fn loads_bare_list_and_versioned_files() {
+        let dir = TempDir::new();
+        let path = dir.path().join("active_recordings.json");
+        let manager = RecordingManager::new(path.clone());
+
+        // version 0, from before the envelope and the later fields
+        std::fs::write(
+            &path,
+            r#"[{"name":"cam1","input_url":"http://example.com/a.m3u8","hls_time":4}]"#,
+        )
+        .unwrap();
+        let reqs = manager.load().await.unwrap();
+        assert_eq!(reqs.len(), 1);
+        assert_eq!(reqs[0].name, "cam1");
+        assert_eq!(reqs[0].hls_time, 4);
+
+        // an unreadable entry is skipped, the others still load
+        std::fs::write(
+            &path,
+            r#"{"version":1,"recordings":[
+                {"name":"cam1","input_url":"http://example.com/a.m3u8","tags":["news"]},
+                {"name":"broken"},
+                {"name":"cam2","input_url":"http://example.com/b.m3u8","resume":true}
+            ]}"#,
+        )
+        .unwrap();
+        let reqs = manager.load().await.unwrap();
+        let names: Vec<_> = reqs.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["cam1", "cam2"]);
+        assert_eq!(reqs[0].tags, ["news"]);
+        assert!(reqs[1].resume);
+
+        std::fs::write(&path, r#"{"version":2,"recordings":[]}"#).unwrap();
+        assert!(manager.load().await.is_err());
+    }
 }